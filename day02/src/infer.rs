@@ -0,0 +1,258 @@
+use crate::{Color, Count, Record, RGB};
+use std::{array, fmt};
+
+/// Extra cubes per color searched beyond the minimal bag when `--slack` isn't given.
+pub const DEFAULT_SLACK: Count = 30;
+
+/// Bags with more cubes than this in total are not estimated.
+const MAX_CUBES: usize = 1 << 16;
+
+/// The most bags searched, every one of them is visited twice.
+const MAX_BAGS: usize = 1 << 24;
+
+/// Probability mass covered by the reported intervals.
+const LEVEL: f64 = 0.95;
+
+/// The estimated bag of a game with an interval per color.
+#[derive(Debug, PartialEq)]
+pub struct Estimate {
+    /// The most likely bag of the search. A color without an upper bound is at
+    /// the search bound, its most likely count is at least that.
+    pub likeliest: RGB,
+    pub intervals: [(Count, Option<Count>); 3],
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, color) in Color::ALL.into_iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            let count = self.likeliest.get(color);
+            match self.intervals[i] {
+                (lo, Some(hi)) => write!(f, "{}{} {} [{}, {}]", sep, count, color, lo, hi)?,
+                (lo, None) => write!(f, "{}at least {} {} [{}, unbounded]", sep, count, color, lo)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn ln_factorials(n: usize) -> Vec<f64> {
+    let mut result = Vec::with_capacity(n + 1);
+    result.push(0.0);
+    for i in 1..=n {
+        result.push(result[i - 1] + (i as f64).ln());
+    }
+    result
+}
+
+fn ln_choose(ln_fact: &[f64], n: usize, k: usize) -> f64 {
+    if k > n {
+        return f64::NEG_INFINITY;
+    }
+    ln_fact[n] - ln_fact[k] - ln_fact[n - k]
+}
+
+/// Index of the first value where the cumulative share of `weights` reaches `p`.
+fn quantile(weights: &[f64], p: f64) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut acc = 0.0;
+    for (i, w) in weights.iter().enumerate() {
+        acc += w;
+        if acc >= p * total {
+            return i;
+        }
+    }
+    weights.len() - 1
+}
+
+/// Estimates the bag of a game assuming every draw takes its cubes out of the bag
+/// without replacement and puts them back before the next draw, so a draw is
+/// multivariate hypergeometric.
+///
+/// Every color is searched from the minimal bag up to `slack` extra cubes and the
+/// bag with the highest likelihood is reported. With a uniform prior each color
+/// also gets the central `LEVEL` interval of its marginal posterior, widened to
+/// contain the most likely count.
+/// Bigger bags look ever more like drawing with replacement and their likelihood
+/// can keep growing, so a color whose most likely count sits on the search bound
+/// is reported as unbounded: its count and interval only bound it from below and
+/// depend on `slack`.
+/// Returns `None` when the minimal bag or the search is too big.
+pub fn estimate(record: &Record, slack: Count) -> Option<Estimate> {
+    let min = record.min_bag();
+    let lower: [usize; 3] = Color::ALL.map(|c| min.get(c) as usize);
+    let upper: [usize; 3] = lower.map(|n| n.saturating_add(slack as usize));
+    let total = upper.iter().fold(0usize, |acc, &n| acc.saturating_add(n));
+    let bags = (slack as usize + 1).checked_pow(3).unwrap_or(usize::MAX);
+    if total > MAX_CUBES || bags > MAX_BAGS {
+        return None;
+    }
+
+    let ln_fact = ln_factorials(total);
+    // The likelihood of a bag splits into a term per color and a term of the bag size.
+    let by_color: [Vec<f64>; 3] = array::from_fn(|i| {
+        (0..=upper[i])
+            .map(|n| {
+                record
+                    .colors
+                    .iter()
+                    .map(|rgb| ln_choose(&ln_fact, n, rgb.get(Color::ALL[i]) as usize))
+                    .sum()
+            })
+            .collect()
+    });
+    let by_size: Vec<f64> = (0..=total)
+        .map(|n| {
+            record
+                .colors
+                .iter()
+                .map(|rgb| ln_choose(&ln_fact, n, (rgb.r + rgb.g + rgb.b) as usize))
+                .sum()
+        })
+        .collect();
+    let ln_likelihood = |r: usize, g: usize, b: usize| {
+        by_color[0][r] + by_color[1][g] + by_color[2][b] - by_size[r + g + b]
+    };
+
+    let mut best = (f64::NEG_INFINITY, [0; 3]);
+    for r in lower[0]..=upper[0] {
+        for g in lower[1]..=upper[1] {
+            for b in lower[2]..=upper[2] {
+                let l = ln_likelihood(r, g, b);
+                if l > best.0 {
+                    best = (l, [r, g, b]);
+                }
+            }
+        }
+    }
+
+    let mut marginals: [Vec<f64>; 3] = upper.map(|n| vec![0.0; n + 1]);
+    for r in lower[0]..=upper[0] {
+        for g in lower[1]..=upper[1] {
+            for b in lower[2]..=upper[2] {
+                let w = (ln_likelihood(r, g, b) - best.0).exp();
+                marginals[0][r] += w;
+                marginals[1][g] += w;
+                marginals[2][b] += w;
+            }
+        }
+    }
+
+    let tail = (1.0 - LEVEL) / 2.0;
+    let intervals = array::from_fn(|i| {
+        let m = &marginals[i];
+        let lo = quantile(m, tail).min(best.1[i]) as Count;
+        let hi = quantile(m, 1.0 - tail).max(best.1[i]) as Count;
+        if best.1[i] == upper[i] && upper[i] > lower[i] {
+            (lo, None)
+        } else {
+            (lo, Some(hi))
+        }
+    });
+    let [r, g, b] = best.1.map(|n| n as Count);
+
+    Some(Estimate {
+        likeliest: RGB { r, g, b },
+        intervals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_single_draw() {
+        let record = &crate::Game::from_str("Game 1: 3 blue, 4 red")
+            .unwrap()
+            .records[0];
+        // Without slack the minimal bag is the only candidate.
+        let estimate = estimate(record, 0).unwrap();
+        assert_eq!(record.min_bag(), estimate.likeliest);
+        assert_eq!(
+            [(4, Some(4)), (0, Some(0)), (3, Some(3))],
+            estimate.intervals
+        );
+    }
+
+    #[test]
+    fn test_example() {
+        let game = crate::Game::from_str(crate::tests::EXAMPLE).unwrap();
+        for record in &game.records {
+            let estimate = estimate(record, DEFAULT_SLACK).unwrap();
+            for (i, color) in Color::ALL.into_iter().enumerate() {
+                let count = estimate.likeliest.get(color);
+                let (lo, hi) = estimate.intervals[i];
+                assert!(lo <= count, "game {}: {}", record.id, estimate);
+                assert!(
+                    hi.is_none_or(|hi| count <= hi),
+                    "game {}: {}",
+                    record.id,
+                    estimate
+                );
+                assert!(record.min_bag().get(color) <= lo);
+            }
+        }
+
+        // Game 1 keeps getting likelier with more blue cubes.
+        let estimate = estimate(&game.records[0], DEFAULT_SLACK).unwrap();
+        assert_eq!(None, estimate.intervals[2].1);
+        assert!(estimate.to_string().contains("at least"));
+    }
+
+    #[test]
+    fn test_search_limit() {
+        let record = &crate::Game::from_str("Game 1: 3 blue, 4 red")
+            .unwrap()
+            .records[0];
+        assert!(estimate(record, 100).is_some());
+        assert!(estimate(record, 3000).is_none());
+        assert!(estimate(record, Count::MAX).is_none());
+    }
+
+    #[test]
+    fn test_estimate() {
+        let record = &crate::Game::from_str("Game 1: 2 red; 2 red; 2 red; 1 red, 1 blue")
+            .unwrap()
+            .records[0];
+        let slack = 10;
+        let estimate = estimate(record, slack).unwrap();
+        let min = record.min_bag();
+        for (i, color) in Color::ALL.into_iter().enumerate() {
+            let (lo, hi) = estimate.intervals[i];
+            let hi = hi.unwrap();
+            assert!(lo >= min.get(color));
+            assert!(lo <= hi && hi <= min.get(color) + slack);
+        }
+
+        // Multiplies the hypergeometric probabilities of the draws directly.
+        let choose =
+            |n: Count, k: Count| (0..k).fold(1.0, |c, i| c * (n - i) as f64 / (i + 1) as f64);
+        let likelihood = |bag: &RGB| {
+            record
+                .colors
+                .iter()
+                .map(|d| {
+                    choose(bag.r, d.r) * choose(bag.g, d.g) * choose(bag.b, d.b)
+                        / choose(bag.r + bag.g + bag.b, d.r + d.g + d.b)
+                })
+                .product::<f64>()
+        };
+        let mut best = (0.0, RGB::default());
+        for r in min.r..=min.r + slack {
+            for g in min.g..=min.g + slack {
+                for b in min.b..=min.b + slack {
+                    let bag = RGB { r, g, b };
+                    let l = likelihood(&bag);
+                    if l > best.0 {
+                        best = (l, bag);
+                    }
+                }
+            }
+        }
+        assert_eq!(best.1, estimate.likeliest);
+        assert!(best.1.r > best.1.g && best.1.r > best.1.b);
+    }
+}
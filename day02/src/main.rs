@@ -1,4 +1,8 @@
-use std::{cmp, env::args, fs, str::FromStr};
+mod infer;
+mod query;
+
+use serde::{Deserialize, Serialize};
+use std::{cmp, env::args, fmt, fs, str::FromStr};
 use utils::dbg;
 
 #[derive(Debug)]
@@ -18,18 +22,83 @@ impl ParseErr {
     }
 }
 
-fn parse_num(val: &str) -> Result<u8, ParseErr> {
-    u8::from_str(val.trim()).or_else(|e| {
-        let msg = format!("{} for {}", e.to_string(), val);
-        Err(ParseErr(msg))
-    })
+/// Cube counts and game ids.
+type Count = u32;
+
+fn parse_num(val: &str) -> Result<Count, ParseErr> {
+    Count::from_str(val.trim()).map_err(|e| ParseErr(format!("{} for {}", e, val)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Color {
+    Red,
+    Green,
+    Blue,
+}
+
+impl Color {
+    const ALL: [Color; 3] = [Color::Red, Color::Green, Color::Blue];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Color::Red => "red",
+            Color::Green => "green",
+            Color::Blue => "blue",
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::ALL
+            .into_iter()
+            .find(|c| c.name() == s.trim())
+            .ok_or_else(|| ParseErr(format!("can't match color name: {}", s)))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 struct RGB {
-    r: u8,
-    g: u8,
-    b: u8,
+    #[serde(rename = "red", default)]
+    r: Count,
+    #[serde(rename = "green", default)]
+    g: Count,
+    #[serde(rename = "blue", default)]
+    b: Count,
+}
+
+impl RGB {
+    fn get(&self, color: Color) -> Count {
+        match color {
+            Color::Red => self.r,
+            Color::Green => self.g,
+            Color::Blue => self.b,
+        }
+    }
+
+    fn set(&mut self, color: Color, count: Count) {
+        match color {
+            Color::Red => self.r = count,
+            Color::Green => self.g = count,
+            Color::Blue => self.b = count,
+        }
+    }
+
+    /// The product of all three counts or `None` if it doesn't fit into `u64`.
+    fn power(&self) -> Option<u64> {
+        (self.r as u64)
+            .checked_mul(self.g as u64)?
+            .checked_mul(self.b as u64)
+    }
 }
 
 impl FromStr for RGB {
@@ -42,30 +111,130 @@ impl FromStr for RGB {
                 .trim()
                 .split_once(' ')
                 .ok_or_else(|| ParseErr("split color and count".to_string()))?;
-            let color_count = parse_num(count)?;
-            match name.trim() {
-                "red" => rgb.r = color_count,
-                "green" => rgb.g = color_count,
-                "blue" => rgb.b = color_count,
-                _ => return Err(ParseErr(format!("can't match color name: {}", name))),
-            };
+            rgb.set(Color::from_str(name)?, parse_num(count)?);
         }
 
         Ok(rgb)
     }
 }
 
-#[derive(Debug)]
+/// Writes the non-zero counts in red, green, blue order, e.g. `4 red, 3 blue`.
+/// An empty draw is written as `0 red` so it can still be parsed back.
+impl fmt::Display for RGB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut counts = Color::ALL
+            .into_iter()
+            .map(|c| (c, self.get(c)))
+            .filter(|(_, count)| *count != 0)
+            .peekable();
+        if counts.peek().is_none() {
+            return write!(f, "0 {}", Color::Red);
+        }
+
+        for (i, (color, count)) in counts.enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} {}", count, color)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Bag used by part 1 when no `--limit` is given.
+const DEFAULT_LIMIT: RGB = RGB {
+    r: 12,
+    g: 13,
+    b: 14,
+};
+
+/// Parses a bag limit like `red=12,green=13,blue=14`.
+/// Colors that are not mentioned keep their value from `DEFAULT_LIMIT`.
+fn parse_limit(s: &str) -> Result<RGB, ParseErr> {
+    let mut limit = DEFAULT_LIMIT;
+    for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+        let (name, count) = pair
+            .split_once('=')
+            .ok_or_else(|| ParseErr(format!("expected color=count in limit: {}", pair)))?;
+        limit.set(Color::from_str(name)?, parse_num(count)?);
+    }
+
+    Ok(limit)
+}
+
+/// The first cube count of a game that doesn't fit into a bag.
+#[derive(Debug, PartialEq)]
+struct Violation {
+    draw: usize,
+    color: Color,
+    count: Count,
+    limit: Count,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct Record {
-    id: u8,
+    id: Count,
+    #[serde(rename = "draws")]
     colors: Vec<RGB>,
 }
 
-#[derive(Debug)]
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Game {}:", self.id)?;
+        for (i, rgb) in self.colors.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ";" };
+            write!(f, "{} {}", sep, rgb)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Record {
+    /// The smallest bag that makes every draw of the game possible.
+    fn min_bag(&self) -> RGB {
+        self.colors.iter().fold(RGB::default(), |mut i, c| {
+            i.r = cmp::max(i.r, c.r);
+            i.g = cmp::max(i.g, c.g);
+            i.b = cmp::max(i.b, c.b);
+
+            i
+        })
+    }
+
+    /// Finds the first draw (and the first color within it) exceeding `limit`.
+    fn violation(&self, limit: &RGB) -> Option<Violation> {
+        self.colors.iter().enumerate().find_map(|(draw, rgb)| {
+            Color::ALL.into_iter().find_map(|color| {
+                let (count, limit) = (rgb.get(color), limit.get(color));
+                (count > limit).then_some(Violation {
+                    draw,
+                    color,
+                    count,
+                    limit,
+                })
+            })
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
 struct Game {
     records: Vec<Record>,
 }
 
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in &self.records {
+            writeln!(f, "{}", record)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl FromStr for Game {
     type Err = ParseErr;
 
@@ -85,7 +254,7 @@ impl FromStr for Game {
 
             let mut colors = Vec::new();
             for raw_record in raw_records.trim().split(';') {
-                let color = RGB::from_str(raw_record).or_else(|err| Err(err.line(i)))?;
+                let color = RGB::from_str(raw_record).map_err(|err| err.line(i))?;
                 colors.push(color);
             }
             records.push(Record { id, colors })
@@ -96,54 +265,356 @@ impl FromStr for Game {
 }
 
 impl Game {
-    fn solve1(&self) -> u32 {
-        let (lr, lg, lb) = (12, 13, 14);
+    /// Like the text format every game needs at least one draw.
+    fn from_json(s: &str) -> Result<Self, ParseErr> {
+        let game: Game =
+            serde_json::from_str(s).map_err(|e| ParseErr(format!("invalid json: {}", e)))?;
+        if let Some(record) = game.records.iter().find(|r| r.colors.is_empty()) {
+            return Err(ParseErr(format!("game {} has no draws", record.id)));
+        }
 
+        Ok(game)
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Game is always serializable")
+    }
+
+    fn solve1(&self, limit: &RGB) -> u64 {
         self.records
             .iter()
-            .filter(|r| {
-                for RGB { r, g, b } in r.colors.clone() {
-                    if lr < r || lg < g || lb < b {
-                        return false;
-                    }
-                }
-
-                true
-            })
-            .map(|r| r.id as u32)
+            .filter(|r| r.violation(limit).is_none())
+            .map(|r| r.id as u64)
             .sum()
     }
 
-    fn solve2(&self) -> u32 {
+    /// Sum of the minimal bag powers or `None` on overflow.
+    fn solve2(&self) -> Option<u64> {
         self.records
             .iter()
-            .map(|r| {
-                let at_least = r.colors.iter().fold(RGB::default(), |mut i, c| {
-                    i.r = cmp::max(i.r, c.r);
-                    i.g = cmp::max(i.g, c.g);
-                    i.b = cmp::max(i.b, c.b);
+            .try_fold(0u64, |sum, r| sum.checked_add(r.min_bag().power()?))
+    }
 
-                    i
-                });
+    /// Prints the minimal bag of every game and the first limit it violates.
+    fn query(&self, limit: &RGB) {
+        for record in &self.records {
+            let verdict = match record.violation(limit) {
+                None => "possible".to_string(),
+                Some(v) => format!(
+                    "violates {} in draw {}: {} > {}",
+                    v.color,
+                    v.draw + 1,
+                    v.count,
+                    v.limit
+                ),
+            };
+            println!(
+                "Game {}: min bag {}; {}",
+                record.id,
+                record.min_bag(),
+                verdict
+            );
+        }
+    }
 
-                at_least.r as u32 * at_least.g as u32 * at_least.b as u32
-            })
-            .sum()
+    /// Prints the games matched by `query` and their aggregates.
+    fn filter(&self, query: &query::Query) {
+        let matches = query::Matches::collect(query, self.records.iter());
+        let ids: Vec<String> = matches.ids.iter().map(|id| id.to_string()).collect();
+        println!("Matched games: {}", ids.join(", "));
+        let power_sum = match matches.power_sum {
+            Some(sum) => sum.to_string(),
+            None => "overflow".to_string(),
+        };
+        println!(
+            "Count: {}. Id sum: {}. Power sum: {}",
+            matches.ids.len(),
+            matches.id_sum,
+            power_sum
+        );
+    }
+
+    /// Prints the estimated bag of every game, see `infer::estimate`.
+    fn infer(&self, slack: Count) {
+        for record in &self.records {
+            match infer::estimate(record, slack) {
+                Some(estimate) => println!("Game {}: {}", record.id, estimate),
+                None => println!("Game {}: bag or --slack is too big to estimate", record.id),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(ParseErr(format!("unknown format: {}", s))),
+        }
+    }
+}
+
+fn next_value(name: &str, args: &mut impl Iterator<Item = String>) -> Result<String, ParseErr> {
+    args.next()
+        .ok_or_else(|| ParseErr(format!("{} expects a value", name)))
+}
+
+#[derive(Debug)]
+struct Options {
+    path: Option<String>,
+    limit: RGB,
+    query: bool,
+    infer: bool,
+    slack: Count,
+    filter: Option<query::Query>,
+    input: Format,
+    emit: Option<Format>,
+}
+
+impl Options {
+    /// Usage: `day02 [path] [--limit red=12,green=13,blue=14] [--query]
+    /// [--infer] [--slack 30] [--where QUERY] [--input text|json] [--emit text|json]`.
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, ParseErr> {
+        let mut opts = Options {
+            path: None,
+            limit: DEFAULT_LIMIT,
+            query: false,
+            infer: false,
+            slack: infer::DEFAULT_SLACK,
+            filter: None,
+            input: Format::Text,
+            emit: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--limit" => opts.limit = parse_limit(&next_value(&arg, &mut args)?)?,
+                "--query" => opts.query = true,
+                "--infer" => opts.infer = true,
+                "--where" => {
+                    opts.filter = Some(query::Query::from_str(&next_value(&arg, &mut args)?)?)
+                }
+                "--slack" => opts.slack = parse_num(&next_value(&arg, &mut args)?)?,
+                "--input" => opts.input = Format::from_str(&next_value(&arg, &mut args)?)?,
+                "--emit" => opts.emit = Some(Format::from_str(&next_value(&arg, &mut args)?)?),
+                _ if arg.starts_with("--") => {
+                    return Err(ParseErr(format!("unknown option {}", arg)))
+                }
+                _ if opts.path.is_some() => {
+                    return Err(ParseErr(format!("unexpected argument {}", arg)))
+                }
+                _ => opts.path = Some(arg),
+            }
+        }
+
+        Ok(opts)
     }
 }
 
 pub fn main() {
-    let given = args()
-        .nth(1)
-        .and_then(|path| fs::read_to_string(path).ok())
-        .unwrap_or("".to_string());
+    let opts = Options::from_args(args().skip(1)).expect("Can't parse arguments");
+    let given = opts.path.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't read {}: {}", path, e))
+    });
 
     let builtin = include_str!("../data.txt");
-    let data = if given.is_empty() { builtin } else { &given };
+    let data = given.as_deref().unwrap_or(builtin);
 
-    let game = Game::from_str(&data).expect("Can't parse puzzle");
-    let result1: u32 = game.solve1();
+    let game = match opts.input {
+        Format::Text => Game::from_str(data),
+        Format::Json => Game::from_json(data),
+    }
+    .expect("Can't parse puzzle");
+    match opts.emit {
+        Some(Format::Text) => print!("{}", game),
+        Some(Format::Json) => println!("{}", game.to_json()),
+        None => (),
+    }
+    if opts.query {
+        game.query(&opts.limit);
+    }
+    if opts.infer {
+        game.infer(opts.slack);
+    }
+    if let Some(query) = &opts.filter {
+        game.filter(query);
+    }
+
+    let result1 = game.solve1(&opts.limit);
     dbg!(result1);
-    let result2: u32 = game.solve2();
+    let result2 = game.solve2().expect("Power sum overflows u64");
     dbg!(result2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub const EXAMPLE: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
+Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
+Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
+Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
+
+    #[test]
+    fn test_solve1_limit() {
+        let game = Game::from_str(EXAMPLE).unwrap();
+        assert_eq!(8, game.solve1(&DEFAULT_LIMIT));
+        assert_eq!(
+            15,
+            game.solve1(&parse_limit("red=20,green=13,blue=15").unwrap())
+        );
+        assert_eq!(0, game.solve1(&parse_limit("red=0").unwrap()));
+    }
+
+    #[test]
+    fn test_violation() {
+        let game = Game::from_str(EXAMPLE).unwrap();
+        assert_eq!(
+            Some(Violation {
+                draw: 0,
+                color: Color::Red,
+                count: 20,
+                limit: 12
+            }),
+            game.records[2].violation(&DEFAULT_LIMIT)
+        );
+        assert_eq!(
+            Some(Violation {
+                draw: 2,
+                color: Color::Red,
+                count: 14,
+                limit: 12
+            }),
+            game.records[3].violation(&DEFAULT_LIMIT)
+        );
+        assert_eq!(None, game.records[0].violation(&DEFAULT_LIMIT));
+        assert_eq!(RGB { r: 14, g: 3, b: 15 }, game.records[3].min_bag());
+    }
+
+    #[test]
+    fn test_wide_counts() {
+        let game = Game::from_str(
+            "Game 1000: 300 red, 70000 green; 4000000000 blue
+Game 70000: 1 red, 1 green, 1 blue; 256 red",
+        )
+        .unwrap();
+
+        assert_eq!(
+            71000,
+            game.solve1(&parse_limit("red=300,green=70000,blue=4000000000").unwrap())
+        );
+        assert_eq!(Some(300 * 70000 * 4000000000 + 256), game.solve2());
+    }
+
+    #[test]
+    fn test_power_overflow() {
+        let max = Count::MAX;
+        let game = Game::from_str(&format!("Game 1: {max} red, {max} green, {max} blue")).unwrap();
+        assert_eq!(None, game.solve2());
+
+        let game = Game::from_str(&format!("Game 1: {max} red, {max} green, 1 blue")).unwrap();
+        assert_eq!(Some(max as u64 * max as u64), game.solve2());
+
+        let game = Game::from_str(&format!(
+            "Game 1: {max} red, {max} green, 1 blue\nGame 2: {max} red, {max} green, 1 blue"
+        ))
+        .unwrap();
+        assert_eq!(None, game.solve2());
+
+        assert!(Game::from_str("Game 1: 4294967296 red").is_err());
+    }
+
+    /// Tiny xorshift generator, enough to produce random games.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+
+        fn game(&mut self) -> Game {
+            let records = (0..self.next(20))
+                .map(|_| Record {
+                    id: self.next(Count::MAX as u64 + 1) as Count,
+                    colors: (0..1 + self.next(6))
+                        .map(|_| {
+                            let mut rgb = RGB::default();
+                            for c in Color::ALL {
+                                // Leave some colors out of the draw.
+                                if self.next(3) != 0 {
+                                    rgb.set(c, self.next(1000) as Count);
+                                }
+                            }
+                            rgb
+                        })
+                        .collect(),
+                })
+                .collect();
+
+            Game { records }
+        }
+    }
+
+    #[test]
+    fn test_args() {
+        let parse = |args: &[&str]| Options::from_args(args.iter().map(|a| a.to_string()));
+        let opts = parse(&["games.txt", "--infer"]).unwrap();
+        assert_eq!(Some("games.txt".to_string()), opts.path);
+        assert!(opts.infer);
+
+        assert_eq!("unknown option --infre", parse(&["--infre"]).unwrap_err().0);
+        assert_eq!(
+            "unknown option --emti",
+            parse(&["games.txt", "--emti", "json"]).unwrap_err().0
+        );
+        assert_eq!(
+            "unexpected argument b.txt",
+            parse(&["a.txt", "b.txt"]).unwrap_err().0
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let game = Game::from_str("Game 1: 3 blue, 4 red; 0 green; 2 green\n").unwrap();
+        assert_eq!("Game 1: 4 red, 3 blue; 0 red; 2 green\n", game.to_string());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = Rng(0x2023_1202);
+        for _ in 0..500 {
+            let game = rng.game();
+            assert_eq!(game, Game::from_str(&game.to_string()).unwrap());
+            assert_eq!(game, Game::from_json(&game.to_json()).unwrap());
+        }
+
+        let game = Game::from_str(EXAMPLE).unwrap();
+        let normalized = game.to_string();
+        assert_eq!(normalized, Game::from_str(&normalized).unwrap().to_string());
+
+        let empty = Game {
+            records: vec![Record {
+                id: 1,
+                colors: Vec::new(),
+            }],
+        };
+        assert!(Game::from_str(&empty.to_string()).is_err());
+        assert_eq!(
+            "game 1 has no draws",
+            Game::from_json(&empty.to_json()).unwrap_err().0
+        );
+    }
+}
@@ -0,0 +1,438 @@
+//! A small filter language over parsed games, e.g.
+//! `max(red) > 10 and count(draws) >= 4` or `any draw has green = 0`.
+//!
+//! ```text
+//! expr  := and ("or" and)*
+//! and   := unary ("and" unary)*
+//! unary := "not" unary | "(" expr ")" | ("any" "draw" "has" | "all" "draws" "have") unary | value op value
+//! value := number | "id" | agg "(" field ")"   -- per game
+//!        | number | color | "total"            -- per draw, inside any/all
+//! agg   := "max" | "min" | "sum" | "count"
+//! field := color | "total" | "draws"
+//! op    := "=" | "!=" | "<" | "<=" | ">" | ">="
+//! ```
+//!
+//! `count(draws)` is the number of draws and `count(red)` the number of draws with red cubes.
+use crate::{Color, Count, ParseErr, Record, RGB};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Num(u64),
+    Op(Op),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn apply(&self, l: u64, r: u64) -> bool {
+        match self {
+            Op::Eq => l == r,
+            Op::Ne => l != r,
+            Op::Lt => l < r,
+            Op::Le => l <= r,
+            Op::Gt => l > r,
+            Op::Ge => l >= r,
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ParseErr> {
+    let b = s.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        let start = i;
+        let token = match b[i] {
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'(' => Token::Open,
+            b')' => Token::Close,
+            b'=' if b.get(i + 1) == Some(&b'=') => {
+                i += 1;
+                Token::Op(Op::Eq)
+            }
+            b'=' => Token::Op(Op::Eq),
+            b'!' if b.get(i + 1) == Some(&b'=') => {
+                i += 1;
+                Token::Op(Op::Ne)
+            }
+            b'<' if b.get(i + 1) == Some(&b'=') => {
+                i += 1;
+                Token::Op(Op::Le)
+            }
+            b'<' => Token::Op(Op::Lt),
+            b'>' if b.get(i + 1) == Some(&b'=') => {
+                i += 1;
+                Token::Op(Op::Ge)
+            }
+            b'>' => Token::Op(Op::Gt),
+            c if c.is_ascii_digit() => {
+                while i + 1 < b.len() && b[i + 1].is_ascii_digit() {
+                    i += 1;
+                }
+                let num = u64::from_str(&s[start..=i])
+                    .map_err(|e| ParseErr(format!("{} for {}", e, &s[start..=i])))?;
+                Token::Num(num)
+            }
+            c if c.is_ascii_alphabetic() => {
+                while i + 1 < b.len() && (b[i + 1].is_ascii_alphanumeric() || b[i + 1] == b'_') {
+                    i += 1;
+                }
+                Token::Word(s[start..=i].to_ascii_lowercase())
+            }
+            _ => {
+                return Err(ParseErr(format!(
+                    "unexpected character at {}: {}",
+                    i,
+                    &s[i..].chars().next().unwrap_or(' ')
+                )))
+            }
+        };
+        tokens.push(token);
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Agg {
+    Max,
+    Min,
+    Sum,
+    Count,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Color(Color),
+    Total,
+    Draws,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Num(u64),
+    Id,
+    Agg(Agg, Field),
+    Color(Color),
+    Total,
+}
+
+fn total(rgb: &RGB) -> u64 {
+    rgb.r as u64 + rgb.g as u64 + rgb.b as u64
+}
+
+impl Value {
+    /// `draw` is set inside `any`/`all`, the parser guarantees that
+    /// per draw values never show up outside of them.
+    fn eval(&self, record: &Record, draw: Option<&RGB>) -> u64 {
+        let field = |rgb: &RGB, field: Field| match field {
+            Field::Color(c) => rgb.get(c) as u64,
+            Field::Total => total(rgb),
+            Field::Draws => 1,
+        };
+        match (self, draw) {
+            (Value::Num(n), _) => *n,
+            (Value::Color(c), Some(rgb)) => rgb.get(*c) as u64,
+            (Value::Total, Some(rgb)) => total(rgb),
+            (Value::Id, _) => record.id as u64,
+            (Value::Agg(agg, f), _) => {
+                let values = record.colors.iter().map(|rgb| field(rgb, *f));
+                match agg {
+                    Agg::Max => values.max().unwrap_or(0),
+                    Agg::Min => values.min().unwrap_or(0),
+                    Agg::Sum => values.sum(),
+                    Agg::Count => values.filter(|&v| v != 0).count() as u64,
+                }
+            }
+            (Value::Color(_) | Value::Total, None) => {
+                unreachable!("per draw value outside of a draw")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Any(Box<Expr>),
+    All(Box<Expr>),
+    Cmp(Value, Op, Value),
+}
+
+impl Expr {
+    fn eval(&self, record: &Record, draw: Option<&RGB>) -> bool {
+        match self {
+            Expr::Or(l, r) => l.eval(record, draw) || r.eval(record, draw),
+            Expr::And(l, r) => l.eval(record, draw) && r.eval(record, draw),
+            Expr::Not(e) => !e.eval(record, draw),
+            Expr::Any(e) => record.colors.iter().any(|rgb| e.eval(record, Some(rgb))),
+            Expr::All(e) => record.colors.iter().all(|rgb| e.eval(record, Some(rgb))),
+            Expr::Cmp(l, op, r) => op.apply(l.eval(record, draw), r.eval(record, draw)),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    in_draw: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseErr> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseErr("unexpected end of query".to_string()))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseErr> {
+        match self.next()? {
+            t if t == expected => Ok(()),
+            t => Err(ParseErr(format!("expected {:?}, got {:?}", expected, t))),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseErr> {
+        let mut left = self.and()?;
+        while self.is_word("or") {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseErr> {
+        let mut left = self.unary()?;
+        while self.is_word("and") {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseErr> {
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.expr()?;
+            self.expect(Token::Close)?;
+            return Ok(expr);
+        }
+        if self.is_word("not") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        for (quantifier, noun, verb) in [("any", "draw", "has"), ("all", "draws", "have")] {
+            if !self.is_word(quantifier) {
+                continue;
+            }
+            if self.in_draw {
+                return Err(ParseErr(format!("nested {} is not supported", quantifier)));
+            }
+            self.pos += 1;
+            self.expect(Token::Word(noun.to_string()))?;
+            self.expect(Token::Word(verb.to_string()))?;
+
+            self.in_draw = true;
+            let body = Box::new(self.unary()?);
+            self.in_draw = false;
+            return Ok(match quantifier {
+                "any" => Expr::Any(body),
+                _ => Expr::All(body),
+            });
+        }
+
+        let left = self.value()?;
+        let op = match self.next()? {
+            Token::Op(op) => op,
+            t => return Err(ParseErr(format!("expected comparison, got {:?}", t))),
+        };
+        let right = self.value()?;
+        Ok(Expr::Cmp(left, op, right))
+    }
+
+    fn value(&mut self) -> Result<Value, ParseErr> {
+        let word = match self.next()? {
+            Token::Num(n) => return Ok(Value::Num(n)),
+            Token::Word(w) => w,
+            t => return Err(ParseErr(format!("expected value, got {:?}", t))),
+        };
+
+        let agg = match word.as_str() {
+            "max" => Some(Agg::Max),
+            "min" => Some(Agg::Min),
+            "sum" => Some(Agg::Sum),
+            "count" => Some(Agg::Count),
+            _ => None,
+        };
+        if let Some(agg) = agg {
+            if self.in_draw {
+                return Err(ParseErr(format!("{} can't be used inside a draw", word)));
+            }
+            self.expect(Token::Open)?;
+            let field = match self.next()? {
+                Token::Word(w) if w == "total" => Field::Total,
+                Token::Word(w) if w == "draws" => Field::Draws,
+                Token::Word(w) => Field::Color(Color::from_str(&w)?),
+                t => return Err(ParseErr(format!("expected field, got {:?}", t))),
+            };
+            self.expect(Token::Close)?;
+            return Ok(Value::Agg(agg, field));
+        }
+
+        let value = match word.as_str() {
+            "id" => return Ok(Value::Id),
+            "total" => Value::Total,
+            _ => Value::Color(Color::from_str(&word)?),
+        };
+        if !self.in_draw {
+            return Err(ParseErr(format!(
+                "{} is a per draw value, use it inside any/all",
+                word
+            )));
+        }
+        Ok(value)
+    }
+}
+
+/// A parsed filter, see the module docs for the syntax.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Query(Expr);
+
+impl FromStr for Query {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            in_draw: false,
+        };
+        let expr = parser.expr()?;
+        if let Some(t) = parser.peek() {
+            return Err(ParseErr(format!("unexpected {:?} after query", t)));
+        }
+
+        Ok(Query(expr))
+    }
+}
+
+impl Query {
+    pub(crate) fn matches(&self, record: &Record) -> bool {
+        self.0.eval(record, None)
+    }
+}
+
+/// Ids of the games matched by a query together with their aggregates.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Matches {
+    pub(crate) ids: Vec<Count>,
+    pub(crate) id_sum: u64,
+    pub(crate) power_sum: Option<u64>,
+}
+
+impl Matches {
+    pub(crate) fn collect<'a>(query: &Query, records: impl Iterator<Item = &'a Record>) -> Self {
+        let mut matches = Matches {
+            ids: Vec::new(),
+            id_sum: 0,
+            power_sum: Some(0),
+        };
+        for record in records.filter(|r| query.matches(r)) {
+            matches.ids.push(record.id);
+            matches.id_sum += record.id as u64;
+            matches.power_sum = matches
+                .power_sum
+                .and_then(|sum| sum.checked_add(record.min_bag().power()?));
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::EXAMPLE, Game};
+
+    fn ids(query: &str) -> Vec<Count> {
+        let game = Game::from_str(EXAMPLE).unwrap();
+        let query = Query::from_str(query).unwrap();
+        Matches::collect(&query, game.records.iter()).ids
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(vec![3, 4], ids("max(red) > 10"));
+        assert_eq!(
+            vec![1, 2, 5],
+            ids("not (max(red) > 12 or max(green) > 13 or max(blue) > 14)")
+        );
+        assert_eq!(vec![1, 3], ids("any draw has green = 0 or id = 3"));
+        assert_eq!(vec![2, 5], ids("all draws have (green > 0 and blue > 0)"));
+        assert_eq!(vec![1, 2, 3, 4], ids("count(draws) >= 3"));
+        assert_eq!(
+            vec![5],
+            ids("count(red) = count(draws) and sum(total) < 20")
+        );
+        assert_eq!(
+            vec![4],
+            ids("min(total) < 10 AND any draw has total >= 30 and max(blue) > 10")
+        );
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let game = Game::from_str(EXAMPLE).unwrap();
+        let query = Query::from_str("max(red) <= 12").unwrap();
+        let matches = Matches::collect(&query, game.records.iter());
+        assert_eq!(vec![1, 2, 5], matches.ids);
+        assert_eq!(8, matches.id_sum);
+        assert_eq!(Some(48 + 12 + 36), matches.power_sum);
+    }
+
+    #[test]
+    fn test_errors() {
+        for query in [
+            "red > 1",
+            "any draw has max(red) > 1",
+            "any draw has any draw has red = 1",
+            "max(red) >",
+            "max(purple) > 1",
+            "(id = 1",
+            "id = 1 id",
+            "id # 1",
+        ] {
+            assert!(Query::from_str(query).is_err(), "{}", query);
+        }
+    }
+}
@@ -1,128 +1,763 @@
-use std::{collections::HashSet, env::args, fs, u8, usize};
+mod stream;
+
+use serde::Serialize;
+use std::{
+    cmp,
+    env::args,
+    fmt::{self, Write},
+    fs,
+    io::{BufRead, BufReader},
+    ops::Range,
+    str::FromStr,
+};
 use utils::dbg;
 
-fn is_symbol(c: u8) -> bool {
-    !c.is_ascii_digit() && c != b'.'
+/// ASCII characters that are neither digits nor symbols.
+#[derive(Debug, Clone, Copy)]
+struct Blanks([bool; 128]);
+
+impl Blanks {
+    fn new(chars: &[u8]) -> Self {
+        let mut blanks = [false; 128];
+        for &c in chars.iter().filter(|c| c.is_ascii()) {
+            blanks[c as usize] = true;
+        }
+        Blanks(blanks)
+    }
+
+    /// `c` must be ASCII, `Schematic::new` rejects everything else.
+    fn is_symbol(&self, c: u8) -> bool {
+        !c.is_ascii_digit() && !self.0[c as usize]
+    }
 }
 
-fn get_frontier(data: &Vec<&[u8]>) -> Vec<Vec<(usize, usize)>> {
-    let (r, d) = (data[0].len(), data.len());
+impl Default for Blanks {
+    fn default() -> Self {
+        Blanks::new(b".")
+    }
+}
 
-    let mut frontier = Vec::new();
-    for (i, row) in data.iter().enumerate() {
-        for (j, &col) in row.iter().enumerate() {
-            if !is_symbol(col) {
-                continue;
+/// Line and column are 1-based.
+#[derive(Debug, PartialEq)]
+enum GridErr {
+    Ragged {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    NonAscii {
+        line: usize,
+        col: usize,
+    },
+    /// The number starting at `col` doesn't fit into a `u64`.
+    Overflow {
+        line: usize,
+        col: usize,
+    },
+    /// The ratio of the gear at `col` doesn't fit into a `u64`.
+    RatioOverflow {
+        line: usize,
+        col: usize,
+    },
+    /// A total stops fitting into a `u64` at the part or gear at `col`.
+    TotalOverflow {
+        line: usize,
+        col: usize,
+    },
+    Io(String),
+}
+
+impl fmt::Display for GridErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridErr::Ragged {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {} has {} columns, expected {}",
+                line, found, expected
+            ),
+            GridErr::NonAscii { line, col } => {
+                write!(f, "non-ASCII character at line {}, column {}", line, col)
             }
-            let mut parts = Vec::new();
-            for di in -1..=1 {
-                for dj in -1..=1 {
-                    if di == 0 && dj == 0 {
-                        continue;
-                    }
-                    let (ni, nj): (usize, usize) =
-                        match (((i as i32) + di).try_into(), ((j as i32) + dj).try_into()) {
-                            (Ok(i), Ok(j)) => (i, j),
-                            _ => continue,
-                        };
-
-                    if ni >= d || nj >= r {
-                        continue;
-                    }
-                    if data[ni][nj].is_ascii_digit() {
-                        parts.push((ni, nj));
-                    }
-                }
+            GridErr::Overflow { line, col } => {
+                write!(f, "number at line {}, column {} is too big", line, col)
+            }
+            GridErr::RatioOverflow { line, col } => {
+                write!(
+                    f,
+                    "ratio of the gear at line {}, column {} is too big",
+                    line, col
+                )
             }
-            frontier.push(parts);
+            GridErr::TotalOverflow { line, col } => {
+                write!(f, "total overflows at line {}, column {}", line, col)
+            }
+            GridErr::Io(e) => write!(f, "can't read schematic: {}", e),
         }
     }
+}
 
-    frontier
+/// A number of the schematic, `cols` is the half-open range of its digits.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Span {
+    row: usize,
+    cols: Range<usize>,
+    value: u64,
 }
 
-#[derive(Default)]
-struct Visitor {
-    visited: HashSet<(usize, usize)>,
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Symbol {
+    row: usize,
+    col: usize,
+    symbol: char,
 }
 
-impl Visitor {
-    fn visit(&mut self, data: &[u8], result: &mut String, i: usize, j: usize) {
-        if self.visited.contains(&(i, j)) || !data[j].is_ascii_digit() {
-            return;
+/// Bipartite graph of symbols and the part numbers touching them.
+/// `edges` are pairs of indices into `symbols` and `parts`.
+#[derive(Debug, Serialize)]
+struct Graph {
+    symbols: Vec<Symbol>,
+    parts: Vec<Span>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl Graph {
+    fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Graph is always serializable")
+    }
+
+    /// Parts attached to more than one symbol are drawn red.
+    fn to_dot(&self) -> String {
+        let mut degree = vec![0; self.parts.len()];
+        for &(_, p) in &self.edges {
+            degree[p] += 1;
         }
 
-        self.visited.insert((i, j));
-        if j > 0 {
-            self.visit(data, result, i, j - 1);
+        let mut dot = String::from("graph schematic {\n");
+        for (i, s) in self.symbols.iter().enumerate() {
+            let symbol = match s.symbol {
+                '"' | '\\' => format!("\\{}", s.symbol),
+                c => c.to_string(),
+            };
+            writeln!(
+                dot,
+                "    s{} [label=\"{} ({}, {})\" shape=box];",
+                i, symbol, s.row, s.col
+            )
+            .unwrap();
         }
-        result.push(data[j] as char);
-        if j + 1 < data.len() {
-            self.visit(data, result, i, j + 1);
+        for (i, p) in self.parts.iter().enumerate() {
+            let color = if degree[i] > 1 { " color=red" } else { "" };
+            writeln!(
+                dot,
+                "    p{} [label=\"{} ({}, {}..{})\"{}];",
+                i, p.value, p.row, p.cols.start, p.cols.end, color
+            )
+            .unwrap();
         }
+        for (s, p) in &self.edges {
+            writeln!(dot, "    s{} -- p{};", s, p).unwrap();
+        }
+        dot.push_str("}\n");
+
+        dot
     }
 }
 
-fn solve1(data: &str) -> u32 {
-    let data: Vec<&[u8]> = data.lines().map(|line| line.trim().as_bytes()).collect();
+struct Schematic<'a> {
+    rows: Vec<&'a [u8]>,
+    blanks: Blanks,
+    spans: Vec<Span>,
+    /// `spans[row_start[i]..row_start[i + 1]]` are the numbers of row `i`.
+    row_start: Vec<usize>,
+}
+
+impl<'a> Schematic<'a> {
+    /// Rows may end with `\n` or `\r\n` and trailing empty lines are ignored,
+    /// otherwise every row must have the same number of ASCII columns as the
+    /// first non-empty one, leading empty lines included.
+    fn new(data: &'a str, blanks: Blanks) -> Result<Self, GridErr> {
+        let mut rows: Vec<&[u8]> = data.lines().map(|line| line.as_bytes()).collect();
+        while rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
+        }
+        let width = rows
+            .iter()
+            .map(|row| row.len())
+            .find(|&n| n > 0)
+            .unwrap_or(0);
+        for (i, row) in rows.iter().enumerate() {
+            if let Some(j) = row.iter().position(|c| !c.is_ascii()) {
+                return Err(GridErr::NonAscii {
+                    line: i + 1,
+                    col: j + 1,
+                });
+            }
+            if row.len() != width {
+                return Err(GridErr::Ragged {
+                    line: i + 1,
+                    expected: width,
+                    found: row.len(),
+                });
+            }
+        }
 
-    let mut frontier = get_frontier(&data);
-    let mut visitor = Visitor::default();
-    let mut results = Vec::new();
-    while let Some(parts) = frontier.pop() {
-        for (i, j) in parts {
-            let mut result = String::new();
-            visitor.visit(data[i], &mut result, i, j);
-            if result.len() == 0 {
-                continue;
+        Schematic::from_rows(rows, blanks)
+    }
+
+    /// Rows must already be validated to be ASCII and of the same width.
+    /// Fails on numbers that don't fit into a `u64`.
+    fn from_rows(rows: Vec<&'a [u8]>, blanks: Blanks) -> Result<Self, GridErr> {
+        let mut spans = Vec::new();
+        let mut row_start = Vec::with_capacity(rows.len() + 1);
+        for (i, row) in rows.iter().enumerate() {
+            row_start.push(spans.len());
+            let mut j = 0;
+            while j < row.len() {
+                if !row[j].is_ascii_digit() {
+                    j += 1;
+                    continue;
+                }
+                let start = j;
+                let mut value: u64 = 0;
+                while j < row.len() && row[j].is_ascii_digit() {
+                    value = value
+                        .checked_mul(10)
+                        .and_then(|v| v.checked_add((row[j] - b'0') as u64))
+                        .ok_or(GridErr::Overflow {
+                            line: i + 1,
+                            col: start + 1,
+                        })?;
+                    j += 1;
+                }
+                spans.push(Span {
+                    row: i,
+                    cols: start..j,
+                    value,
+                });
             }
+        }
+        row_start.push(spans.len());
+
+        Ok(Schematic {
+            rows,
+            blanks,
+            spans,
+            row_start,
+        })
+    }
+
+    fn symbols(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rows.iter().enumerate().flat_map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(_, &c)| self.blanks.is_symbol(c))
+                .map(move |(j, _)| (i, j))
+        })
+    }
+
+    /// Whether a symbol touches the number, diagonals included.
+    fn is_part(&self, span: &Span) -> bool {
+        let rows = span.row.saturating_sub(1)..cmp::min(span.row + 2, self.rows.len());
+        self.rows[rows].iter().any(|row| {
+            let cols = span.cols.start.saturating_sub(1)..cmp::min(span.cols.end + 1, row.len());
+            row[cols].iter().any(|&c| self.blanks.is_symbol(c))
+        })
+    }
+
+    /// Indices into `spans` of the numbers touching the cell, diagonals included.
+    fn adjacent(&self, i: usize, j: usize) -> impl Iterator<Item = usize> + '_ {
+        let rows = i.saturating_sub(1)..cmp::min(i + 2, self.rows.len());
+        rows.flat_map(move |r| {
+            let offset = self.row_start[r];
+            let row = &self.spans[offset..self.row_start[r + 1]];
+            // Spans of a row are sorted and don't overlap, so both bounds are monotonic.
+            let from = row.partition_point(|s| s.cols.end < j);
+            let to = row.partition_point(|s| s.cols.start <= j + 1);
+            offset + from..offset + to
+        })
+    }
+
+    /// Keeps only the numbers that touch at least one symbol.
+    fn graph(&self) -> Graph {
+        let mut graph = Graph {
+            symbols: Vec::new(),
+            parts: Vec::new(),
+            edges: Vec::new(),
+        };
+        let mut part_ids = vec![None; self.spans.len()];
+        for (i, j) in self.symbols() {
+            let symbol = graph.symbols.len();
+            graph.symbols.push(Symbol {
+                row: i,
+                col: j,
+                symbol: self.rows[i][j] as char,
+            });
+            for part in self.adjacent(i, j) {
+                let id = *part_ids[part].get_or_insert_with(|| {
+                    graph.parts.push(self.spans[part].clone());
+                    graph.parts.len() - 1
+                });
+                graph.edges.push((symbol, id));
+            }
+        }
+
+        graph
+    }
+}
+
+/// Adds the part or gear at `row` and `col` of the schematic to `total`.
+fn add_total(total: u64, value: u64, row: usize, col: usize) -> Result<u64, GridErr> {
+    total.checked_add(value).ok_or(GridErr::TotalOverflow {
+        line: row + 1,
+        col: col + 1,
+    })
+}
+
+/// Parts are added in reading order, so an overflow is reported at the same part
+/// as by `Stream`.
+fn solve1(schematic: &Schematic) -> Result<u64, GridErr> {
+    let mut counted = vec![false; schematic.spans.len()];
+    for (i, j) in schematic.symbols() {
+        for part in schematic.adjacent(i, j) {
+            counted[part] = true;
+        }
+    }
+
+    schematic
+        .spans
+        .iter()
+        .zip(counted)
+        .filter(|(_, counted)| *counted)
+        .try_fold(0, |total, (span, _)| {
+            add_total(total, span.value, span.row, span.cols.start)
+        })
+}
+
+/// How many parts a gear must touch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl FromStr for Arity {
+    type Err = String;
+
+    /// `2` means exactly two parts and `2+` at least two.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |v: &str| v.parse::<usize>().map_err(|e| format!("{} for {}", e, s));
+        match s.strip_suffix('+') {
+            Some(v) => parse(v).map(Arity::AtLeast),
+            None => parse(s).map(Arity::Exactly),
+        }
+    }
+}
+
+/// How the parts of a gear are combined into its ratio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregation {
+    Product,
+    Sum,
+    Max,
+}
+
+impl FromStr for Aggregation {
+    type Err = String;
 
-            results.push(result.parse::<u32>().expect("cant parse"))
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "product" => Ok(Aggregation::Product),
+            "sum" => Ok(Aggregation::Sum),
+            "max" => Ok(Aggregation::Max),
+            _ => Err(format!("unknown aggregation: {}", s)),
         }
     }
+}
 
-    results.iter().sum()
+#[derive(Debug, Clone, PartialEq)]
+struct GearRule {
+    /// Symbols that can be gears, `None` allows any symbol.
+    symbols: Option<Vec<u8>>,
+    arity: Arity,
+    aggregation: Aggregation,
 }
 
-fn solve2(data: &str) -> u32 {
-    let data: Vec<&[u8]> = data.lines().map(|line| line.trim().as_bytes()).collect();
+impl GearRule {
+    /// Part 2: a `*` touching exactly two parts, the ratio is their product.
+    fn part2() -> Self {
+        GearRule {
+            symbols: Some(vec![b'*']),
+            arity: Arity::Exactly(2),
+            aggregation: Aggregation::Product,
+        }
+    }
 
-    let mut frontier = get_frontier(&data);
-    let mut visitor = Visitor::default();
-    let mut results = Vec::new();
-    while let Some(parts) = frontier.pop() {
-        let mut parts_numbers = Vec::new();
-        for (i, j) in parts {
-            let mut result = String::new();
-            visitor.visit(data[i], &mut result, i, j);
-            if result.len() == 0 {
-                continue;
+    /// The ratio of the symbol at `row` and `col`, `None` if it isn't a gear.
+    fn ratio(
+        &self,
+        symbol: u8,
+        parts: impl Iterator<Item = u64>,
+        row: usize,
+        col: usize,
+    ) -> Result<Option<u64>, GridErr> {
+        if let Some(symbols) = &self.symbols {
+            if !symbols.contains(&symbol) {
+                return Ok(None);
             }
-            let number = result.parse::<u32>().expect("cant parse");
-            parts_numbers.push(number);
         }
-        if parts_numbers.len() != 2 {
-            continue;
+
+        let (mut count, mut ratio, mut overflow) = (0, None::<u64>, false);
+        for v in parts {
+            let next = match (ratio, self.aggregation) {
+                (None, _) => Some(v),
+                (Some(r), Aggregation::Product) => r.checked_mul(v),
+                (Some(r), Aggregation::Sum) => r.checked_add(v),
+                (Some(r), Aggregation::Max) => Some(cmp::max(r, v)),
+            };
+            overflow |= next.is_none();
+            ratio = next.or(ratio);
+            count += 1;
         }
+        let fits = match self.arity {
+            Arity::Exactly(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+        };
 
-        results.push(parts_numbers.iter().product());
+        if !fits {
+            Ok(None)
+        } else if overflow {
+            Err(GridErr::RatioOverflow {
+                line: row + 1,
+                col: col + 1,
+            })
+        } else {
+            Ok(Some(ratio.unwrap_or(0)))
+        }
     }
+}
+
+impl Default for GearRule {
+    fn default() -> Self {
+        Self::part2()
+    }
+}
 
-    results.iter().sum()
+fn solve2(schematic: &Schematic, rule: &GearRule) -> Result<u64, GridErr> {
+    schematic.symbols().try_fold(0, |total, (i, j)| {
+        let parts = schematic.adjacent(i, j).map(|p| schematic.spans[p].value);
+        match rule.ratio(schematic.rows[i][j], parts, i, j)? {
+            Some(ratio) => add_total(total, ratio, i, j),
+            None => Ok(total),
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GraphFormat {
+    Dot,
+    Json,
+}
+
+fn next_value(name: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} expects a value", name))
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    path: Option<String>,
+    graph: Option<GraphFormat>,
+    gears: GearRule,
+    blanks: Blanks,
+    stream: bool,
+}
+
+impl Options {
+    /// Usage: `day03 [path] [--graph dot|json] [--gear-symbols '*' | --gear-any-symbol]
+    /// [--gear-parts 2|2+] [--gear-agg product|sum|max] [--blanks '.'] [--stream]`,
+    /// gear options default to part 2.
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut opts = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--graph" => {
+                    opts.graph = match args.next().as_deref() {
+                        Some("dot") => Some(GraphFormat::Dot),
+                        Some("json") => Some(GraphFormat::Json),
+                        v => return Err(format!("--graph expects dot or json, got {:?}", v)),
+                    }
+                }
+                "--gear-symbols" => {
+                    opts.gears.symbols = Some(next_value(&arg, &mut args)?.into_bytes())
+                }
+                "--stream" => opts.stream = true,
+                "--blanks" => opts.blanks = Blanks::new(next_value(&arg, &mut args)?.as_bytes()),
+                "--gear-any-symbol" => opts.gears.symbols = None,
+                "--gear-parts" => {
+                    opts.gears.arity = Arity::from_str(&next_value(&arg, &mut args)?)?
+                }
+                "--gear-agg" => {
+                    opts.gears.aggregation = Aggregation::from_str(&next_value(&arg, &mut args)?)?
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if opts.path.is_some() => return Err(format!("unexpected argument {}", arg)),
+                _ => opts.path = Some(arg),
+            }
+        }
+
+        Ok(opts)
+    }
+}
+
+/// Prints every part and gear as soon as it is read instead of loading the whole schematic.
+fn stream(reader: impl BufRead, opts: &Options) {
+    let (result1, result2) = stream::Stream::new(reader, opts.blanks, opts.gears.clone())
+        .totals(|event| match event {
+            stream::Event::Part(span) => println!(
+                "part {} at {}:{}..{}",
+                span.value, span.row, span.cols.start, span.cols.end
+            ),
+            stream::Event::Gear { row, col, ratio } => {
+                println!("gear {} at {}:{}", ratio, row, col)
+            }
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    dbg!(result1);
+    dbg!(result2);
 }
 
 pub fn main() {
-    let given = args()
-        .nth(1)
-        .and_then(|path| fs::read_to_string(path).ok())
-        .unwrap_or("".to_string());
+    let opts = Options::from_args(args().skip(1)).expect("Can't parse arguments");
+    if opts.stream {
+        match &opts.path {
+            Some(path) => {
+                let file = fs::File::open(path).expect("Can't open schematic");
+                stream(BufReader::new(file), &opts)
+            }
+            None => stream(include_str!("../data.txt").as_bytes(), &opts),
+        }
+        return;
+    }
+
+    let given = opts.path.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't read {}: {}", path, e))
+    });
 
     let builtin = include_str!("../data.txt");
-    let data = if given.is_empty() { builtin } else { &given };
+    let data = given.as_deref().unwrap_or(builtin);
 
-    let result1 = solve1(&data);
+    let schematic = Schematic::new(data, opts.blanks).unwrap_or_else(|e| panic!("{}", e));
+    match opts.graph {
+        Some(GraphFormat::Dot) => print!("{}", schematic.graph().to_dot()),
+        Some(GraphFormat::Json) => println!("{}", schematic.graph().to_json()),
+        None => (),
+    }
+
+    let result1 = solve1(&schematic).unwrap_or_else(|e| panic!("{}", e));
     dbg!(result1);
 
-    let result2 = solve2(&data);
+    let result2 = solve2(&schematic, &opts.gears).unwrap_or_else(|e| panic!("{}", e));
     dbg!(result2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub const EXAMPLE: &str = "467..114..
+...*......
+..35..633.
+......#...
+617*......
+.....+.58.
+..592.....
+......755.
+...$.*....
+.664.598..";
+
+    fn parse(data: &str) -> Schematic<'_> {
+        Schematic::new(data, Blanks::default()).unwrap()
+    }
+
+    #[test]
+    fn test_example() {
+        assert_eq!(Ok(4361), solve1(&parse(EXAMPLE)));
+        assert_eq!(Ok(467835), solve2(&parse(EXAMPLE), &GearRule::part2()));
+    }
+
+    #[test]
+    fn test_spans() {
+        let schematic = parse("12.*\n..3.\n45*6");
+        let values: Vec<u64> = schematic.spans.iter().map(|s| s.value).collect();
+        assert_eq!(vec![12, 3, 45, 6], values);
+        assert_eq!(2..3, schematic.spans[1].cols);
+
+        let adjacent: Vec<u64> = schematic
+            .adjacent(2, 2)
+            .map(|p| schematic.spans[p].value)
+            .collect();
+        assert_eq!(vec![3, 45, 6], adjacent);
+        // A number touching two symbols is counted once.
+        assert_eq!(Ok(3 + 45 + 6), solve1(&parse("12.*\n..3.\n45*6")));
+    }
+
+    #[test]
+    fn test_graph() {
+        let graph = parse("12.*\n..3.\n45*6").graph();
+        assert_eq!(
+            vec![
+                Symbol {
+                    row: 0,
+                    col: 3,
+                    symbol: '*'
+                },
+                Symbol {
+                    row: 2,
+                    col: 2,
+                    symbol: '*'
+                }
+            ],
+            graph.symbols
+        );
+        let parts: Vec<u64> = graph.parts.iter().map(|p| p.value).collect();
+        assert_eq!(vec![3, 45, 6], parts);
+        assert_eq!(vec![(0, 0), (1, 0), (1, 1), (1, 2)], graph.edges);
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("p0 [label=\"3 (1, 2..3)\" color=red];"));
+        assert!(dot.contains("s1 -- p2;"));
+
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json()).unwrap();
+        assert_eq!(45, json["parts"][1]["value"]);
+        assert_eq!('*'.to_string(), json["symbols"][0]["symbol"]);
+    }
+
+    #[test]
+    fn test_gear_rules() {
+        let rule = |symbols: Option<&str>, arity: &str, aggregation: &str| GearRule {
+            symbols: symbols.map(|s| s.as_bytes().to_vec()),
+            arity: Arity::from_str(arity).unwrap(),
+            aggregation: Aggregation::from_str(aggregation).unwrap(),
+        };
+
+        assert_eq!(GearRule::part2(), rule(Some("*"), "2", "product"));
+        // Only the `*` gears touch two parts in the example.
+        assert_eq!(
+            Ok(467835),
+            solve2(&parse(EXAMPLE), &rule(None, "2", "product"))
+        );
+        let data = EXAMPLE.replace("...*......", "...#......");
+        assert_eq!(
+            Ok(467835 - 467 * 35),
+            solve2(&parse(&data), &GearRule::part2())
+        );
+        assert_eq!(
+            Ok(467835),
+            solve2(&parse(&data), &rule(None, "2", "product"))
+        );
+        assert_eq!(
+            Ok(467 + 35 + 755 + 598),
+            solve2(&parse(EXAMPLE), &rule(Some("*"), "2", "sum"))
+        );
+        assert_eq!(
+            Ok(467 + 617 + 755),
+            solve2(&parse(EXAMPLE), &rule(Some("*"), "1+", "max"))
+        );
+        assert_eq!(
+            Ok(617),
+            solve2(&parse(EXAMPLE), &rule(Some("*"), "1", "sum"))
+        );
+        assert_eq!(
+            Ok(633 + 592 + 664),
+            solve2(&parse(EXAMPLE), &rule(Some("#+$"), "0+", "sum"))
+        );
+        // Two 10-digit parts around a gear.
+        assert_eq!(
+            Err(GridErr::RatioOverflow { line: 1, col: 11 }),
+            solve2(&parse("9999999999*9999999999"), &GearRule::part2())
+        );
+        assert_eq!(
+            Ok(19999999998),
+            solve2(
+                &parse("9999999999*9999999999"),
+                &rule(Some("*"), "2", "sum")
+            )
+        );
+        assert_eq!(
+            Ok(0),
+            solve2(
+                &parse("9999999999*9999999999"),
+                &rule(Some("*"), "3", "product")
+            )
+        );
+        assert_eq!(
+            Err(GridErr::TotalOverflow { line: 2, col: 2 }),
+            solve2(
+                &parse("18446744073709551615*1\n.*...................."),
+                &rule(Some("*"), "1+", "max")
+            )
+        );
+
+        assert!(Arity::from_str("two").is_err());
+        assert!(Aggregation::from_str("min").is_err());
+    }
+
+    #[test]
+    fn test_args() {
+        let parse = |args: &[&str]| Options::from_args(args.iter().map(|a| a.to_string()));
+        let opts = parse(&["schematic.txt", "--stream"]).unwrap();
+        assert_eq!(Some("schematic.txt".to_string()), opts.path);
+        assert!(opts.stream);
+
+        assert_eq!(
+            Err("unknown option --graf".to_string()),
+            parse(&["schematic.txt", "--graf", "dot"]).map(|_| ())
+        );
+        assert_eq!(
+            Err("unexpected argument b.txt".to_string()),
+            parse(&["a.txt", "b.txt"]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_grid_errors() {
+        let crlf = EXAMPLE.replace('\n', "\r\n") + "\r\n\r\n";
+        assert_eq!(Ok(4361), solve1(&parse(&crlf)));
+
+        assert_eq!(
+            Some(GridErr::Ragged {
+                line: 2,
+                expected: 3,
+                found: 2
+            }),
+            Schematic::new("1.2\n*.\n...", Blanks::default()).err()
+        );
+        assert_eq!(
+            Some(GridErr::NonAscii { line: 1, col: 3 }),
+            Schematic::new("1.é\n...", Blanks::default()).err()
+        );
+        assert_eq!(
+            Some(GridErr::Overflow { line: 2, col: 1 }),
+            Schematic::new(
+                "..........................\n9999999999999999999999999*",
+                Blanks::default()
+            )
+            .err()
+        );
+        assert_eq!(
+            Err(GridErr::TotalOverflow { line: 1, col: 22 }),
+            solve1(&parse("18446744073709551615*1"))
+        );
+
+        // Spaces are symbols unless they are blanks.
+        let data = "12 .\n....";
+        assert_eq!(Ok(12), solve1(&parse(data)));
+        let schematic = Schematic::new(data, Blanks::new(b". ")).unwrap();
+        assert_eq!(Ok(0), solve1(&schematic));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{add_total, Blanks, GearRule, GridErr, Schematic, Span};
+use std::{collections::VecDeque, io::BufRead};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Event {
+    Part(Span),
+    Gear { row: usize, col: usize, ratio: u64 },
+}
+
+/// Reads a schematic row by row and keeps only the rows around the one being
+/// processed, so the input never has to fit into memory.
+///
+/// A row is processed once the next one is read: its part numbers are emitted
+/// first, then the gears found in it. Validation matches `Schematic::new`.
+pub(crate) struct Stream<R> {
+    reader: R,
+    blanks: Blanks,
+    rule: GearRule,
+    /// At most three rows, `window[0]` is row `first` of the schematic.
+    window: VecDeque<Vec<u8>>,
+    first: usize,
+    /// The next row to process.
+    next: usize,
+    width: Option<usize>,
+    line: usize,
+    /// The first of the empty lines read since the last row.
+    empty_from: Option<usize>,
+    eof: bool,
+    failed: bool,
+    pending: VecDeque<Result<Event, GridErr>>,
+}
+
+impl<R: BufRead> Stream<R> {
+    pub(crate) fn new(reader: R, blanks: Blanks, rule: GearRule) -> Self {
+        Stream {
+            reader,
+            blanks,
+            rule,
+            window: VecDeque::with_capacity(3),
+            first: 0,
+            next: 0,
+            width: None,
+            line: 0,
+            empty_from: None,
+            eof: false,
+            failed: false,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn read_row(&mut self) -> Result<Option<Vec<u8>>, GridErr> {
+        let mut row = Vec::new();
+        loop {
+            row.clear();
+            let read = self
+                .reader
+                .read_until(b'\n', &mut row)
+                .map_err(|e| GridErr::Io(e.to_string()))?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if row.last() == Some(&b'\n') {
+                row.pop();
+                if row.last() == Some(&b'\r') {
+                    row.pop();
+                }
+            }
+
+            if row.is_empty() {
+                self.empty_from.get_or_insert(self.line);
+                continue;
+            }
+            let width = *self.width.get_or_insert(row.len());
+            if let Some(line) = self.empty_from {
+                return Err(GridErr::Ragged {
+                    line,
+                    expected: width,
+                    found: 0,
+                });
+            }
+            if let Some(j) = row.iter().position(|c| !c.is_ascii()) {
+                return Err(GridErr::NonAscii {
+                    line: self.line,
+                    col: j + 1,
+                });
+            }
+            if row.len() != width {
+                return Err(GridErr::Ragged {
+                    line: self.line,
+                    expected: width,
+                    found: row.len(),
+                });
+            }
+
+            return Ok(Some(row));
+        }
+    }
+
+    fn process(&mut self) -> Result<(), GridErr> {
+        let rows = self.window.iter().map(|row| row.as_slice()).collect();
+        let schematic = Schematic::from_rows(rows, self.blanks).map_err(|e| match e {
+            GridErr::Overflow { line, col } => GridErr::Overflow {
+                line: self.first + line,
+                col,
+            },
+            e => e,
+        })?;
+        let center = self.next - self.first;
+
+        let spans = &schematic.spans[schematic.row_start[center]..schematic.row_start[center + 1]];
+        for span in spans.iter().filter(|span| schematic.is_part(span)) {
+            self.pending.push_back(Ok(Event::Part(Span {
+                row: self.first + span.row,
+                ..span.clone()
+            })));
+        }
+
+        for (i, j) in schematic.symbols().filter(|&(i, _)| i == center) {
+            let parts = schematic.adjacent(i, j).map(|p| schematic.spans[p].value);
+            let ratio = self.rule.ratio(schematic.rows[i][j], parts, self.next, j);
+            if let Some(ratio) = ratio.transpose() {
+                self.pending.push_back(ratio.map(|ratio| Event::Gear {
+                    row: self.next,
+                    col: j,
+                    ratio,
+                }));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fill(&mut self) -> Result<(), GridErr> {
+        while self.pending.is_empty() {
+            if !self.eof {
+                match self.read_row()? {
+                    Some(row) => self.window.push_back(row),
+                    None => self.eof = true,
+                }
+            }
+
+            let end = self.first + self.window.len();
+            if self.next < end && (self.next + 1 < end || self.eof) {
+                self.process()?;
+                self.next += 1;
+                while self.first + 1 < self.next {
+                    self.window.pop_front();
+                    self.first += 1;
+                }
+            } else if self.eof {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Stream<R> {
+    /// Adds up the parts and the gear ratios and passes every event to `each`.
+    ///
+    /// Fails like `Schematic::new`, `solve1` and `solve2` one after the other: an
+    /// error in the grid comes first, then an overflow of the parts and then one of
+    /// the gears, so reading goes on after an overflow.
+    pub(crate) fn totals(self, mut each: impl FnMut(&Event)) -> Result<(u64, u64), GridErr> {
+        let (mut parts, mut gears) = (Ok(0), Ok(0));
+        for event in self {
+            match event {
+                Ok(event) => {
+                    each(&event);
+                    match event {
+                        Event::Part(span) => {
+                            parts = parts.and_then(|total| {
+                                add_total(total, span.value, span.row, span.cols.start)
+                            })
+                        }
+                        Event::Gear { row, col, ratio } => {
+                            gears = gears.and_then(|total| add_total(total, ratio, row, col))
+                        }
+                    }
+                }
+                Err(e @ GridErr::RatioOverflow { .. }) => gears = gears.and(Err(e)),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok((parts?, gears?))
+    }
+}
+
+impl<R: BufRead> Iterator for Stream<R> {
+    type Item = Result<Event, GridErr>;
+
+    /// Stops after the first error in the grid, a gear whose ratio overflows is
+    /// an error in place of its event.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if let Err(e) = self.fill() {
+            self.failed = true;
+            return Some(Err(e));
+        }
+
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{solve1, solve2, tests::EXAMPLE};
+
+    fn totals(data: &str) -> Result<(u64, u64), GridErr> {
+        Stream::new(data.as_bytes(), Blanks::default(), GearRule::part2()).totals(|_| ())
+    }
+
+    fn in_memory(data: &str) -> Result<(u64, u64), GridErr> {
+        let schematic = Schematic::new(data, Blanks::default())?;
+        Ok((solve1(&schematic)?, solve2(&schematic, &GearRule::part2())?))
+    }
+
+    #[test]
+    fn test_example() {
+        assert_eq!(Ok((4361, 467835)), totals(EXAMPLE));
+        assert_eq!(Ok((4361, 467835)), totals(&EXAMPLE.replace('\n', "\r\n")));
+
+        let events: Vec<Event> =
+            Stream::new(EXAMPLE.as_bytes(), Blanks::default(), GearRule::part2())
+                .take(3)
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(
+            vec![
+                Event::Part(Span {
+                    row: 0,
+                    cols: 0..3,
+                    value: 467
+                }),
+                Event::Gear {
+                    row: 1,
+                    col: 3,
+                    ratio: 467 * 35
+                },
+                Event::Part(Span {
+                    row: 2,
+                    cols: 2..4,
+                    value: 35
+                }),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn test_matches_in_memory() {
+        let mut seed: u32 = 2023;
+        for size in [1, 2, 3, 7, 40] {
+            let data: String = (0..size)
+                .map(|_| {
+                    (0..size)
+                        .map(|_| {
+                            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                            b"....1234*#"[(seed >> 16) as usize % 10] as char
+                        })
+                        .collect::<String>()
+                        + "\n"
+                })
+                .collect();
+            let expected = in_memory(&data);
+            assert!(expected.is_ok());
+            assert_eq!(expected, totals(&data), "{}", data);
+        }
+    }
+
+    #[test]
+    fn test_errors_match_in_memory() {
+        for data in [
+            "\n12*\n...\n",
+            "\n\n12*\n",
+            "\r\n12*\r\n...\r\n",
+            "\n1.é\n",
+            "1.2\n\n*..\n",
+            "1.2\n*.\n...\n",
+            "1.é\n...\n",
+            "..........................\n9999999999999999999999999*\n",
+            "18446744073709551615*1\n",
+            "9999999999*9999999999\n",
+            "9999999999*9999999999\n18446744073709551615*\n",
+            "9999999999*9999999999\n.....................\n18446744073709551615*\n",
+            "9999999999*9999999999\n.....................\n1*\n",
+        ] {
+            let expected = in_memory(data);
+            assert!(expected.is_err(), "{:?}", data);
+            assert_eq!(expected, totals(data), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Err(GridErr::Ragged {
+                line: 3,
+                expected: 3,
+                found: 2
+            }),
+            totals("1.2\n*..\n*.\n")
+        );
+        assert_eq!(
+            Err(GridErr::Ragged {
+                line: 2,
+                expected: 3,
+                found: 0
+            }),
+            totals("1.2\n\n*..\n")
+        );
+        assert_eq!(Ok((12, 0)), totals("12*\n...\n\n\n"));
+        assert_eq!(
+            Err(GridErr::RatioOverflow { line: 1, col: 11 }),
+            totals("9999999999*9999999999\n")
+        );
+        assert_eq!(
+            Err(GridErr::Ragged {
+                line: 1,
+                expected: 3,
+                found: 0
+            }),
+            totals("\n12*\n...\n")
+        );
+        assert_eq!(
+            Err(GridErr::Overflow { line: 2, col: 1 }),
+            totals("..........................\n9999999999999999999999999*\n")
+        );
+        assert_eq!(
+            Ok((u64::MAX, 0)),
+            totals("18446744073709551615*\n.....................\n")
+        );
+        assert_eq!(
+            Err(GridErr::TotalOverflow { line: 3, col: 1 }),
+            totals("9999999999*9999999999\n.....................\n18446744073709551615*\n")
+        );
+    }
+}
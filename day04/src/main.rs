@@ -1,59 +1,532 @@
-use std::{
-    collections::{HashMap, HashSet},
-    env::args,
-    fs,
-};
+use std::{cmp, collections::HashSet, env::args, fmt, fs};
 use utils::dbg;
 
-fn count_wins(line: &str) -> u32 {
-    let (wins, nums) = line.split_once('|').expect("|");
-    let winning_nums = wins
-        .split(' ')
-        .filter_map(|v| v.parse::<u32>().ok())
-        .collect::<HashSet<u32>>();
+#[derive(Debug, Clone, PartialEq)]
+struct Card {
+    id: usize,
+    winning: HashSet<u32>,
+    numbers: Vec<u32>,
+}
+
+impl Card {
+    /// Parses `Card N: winning numbers | numbers`, `line` is 1-based.
+    fn parse(s: &str, line: usize) -> Result<Self, CardErr> {
+        let malformed = |msg: String| CardErr::Malformed { line, msg };
+        let (head, body) = s
+            .split_once(':')
+            .ok_or_else(|| malformed("missing ':' after card id".to_string()))?;
+        let id = head
+            .trim()
+            .strip_prefix("Card")
+            .ok_or_else(|| malformed(format!("expected 'Card N', got '{}'", head)))?
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| malformed(format!("card id {}: {}", head, e)))?;
+        let (wins, nums) = body
+            .split_once('|')
+            .ok_or_else(|| malformed("missing '|' between number lists".to_string()))?;
+        let parse_nums = |list: &str| {
+            list.split_whitespace()
+                .map(|v| {
+                    v.parse::<u32>()
+                        .map_err(|e| malformed(format!("{} for {}", e, v)))
+                })
+                .collect::<Result<Vec<u32>, CardErr>>()
+        };
+
+        let mut winning = HashSet::new();
+        for number in parse_nums(wins)? {
+            if !winning.insert(number) {
+                return Err(CardErr::DuplicateNumber { line, number });
+            }
+        }
+
+        Ok(Card {
+            id,
+            winning,
+            numbers: parse_nums(nums)?,
+        })
+    }
+
+    /// `None` if the count doesn't fit into `u32`.
+    fn matches(&self) -> Option<u32> {
+        self.numbers
+            .iter()
+            .filter(|v| self.winning.contains(v))
+            .count()
+            .try_into()
+            .ok()
+    }
+}
+
+/// Skips blank lines and checks that ids go 1, 2, 3, ...
+fn parse_cards(data: &str) -> Result<Vec<Card>, CardErr> {
+    let mut cards: Vec<Card> = Vec::new();
+    let mut lines = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let card = Card::parse(line, i + 1)?;
+        let expected = cards.len() + 1;
+        if (1..expected).contains(&card.id) {
+            return Err(CardErr::DuplicateId {
+                line: i + 1,
+                id: card.id,
+                first_line: lines[card.id - 1],
+            });
+        }
+        if card.id != expected {
+            return Err(CardErr::NonSequential {
+                line: i + 1,
+                expected,
+                found: card.id,
+            });
+        }
+        cards.push(card);
+        lines.push(i + 1);
+    }
 
-    nums.split(' ')
-        .filter_map(|v| v.parse::<u32>().ok().and_then(|v| winning_nums.get(&v)))
-        .count() as u32
+    Ok(cards)
 }
 
-fn solve1(data: &str) -> u32 {
-    data.lines()
-        .map(|line| match count_wins(line) {
-            wins @ 1.. => 2u32.pow(wins - 1),
-            _ => 0,
+fn count_wins(cards: &[Card]) -> Result<Vec<u32>, CardErr> {
+    cards
+        .iter()
+        .map(|card| {
+            card.matches()
+                .ok_or_else(|| overflow::<u32>(card.id - 1, "matches"))
         })
-        .sum()
+        .collect()
 }
 
-fn solve2(data: &str) -> u32 {
-    data.lines()
-        .enumerate()
-        .scan(HashMap::new(), |h, line| {
-            let count = *h.entry(line.0).and_modify(|v| *v += 1).or_insert(1);
-            let wins = count_wins(line.1);
-            for i in 1..=wins as usize {
-                let k = line.0 + i;
-                h.entry(k).and_modify(|v| *v += count).or_insert(count);
+/// Unsigned integers used to count points and copies.
+trait Counter: Copy + Ord + fmt::Display + fmt::Debug {
+    const ZERO: Self;
+    const ONE: Self;
+    const NAME: &'static str;
+
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn checked_shl(self, rhs: u32) -> Option<Self>;
+}
+
+macro_rules! impl_counter {
+    ($($t:ty),*) => {
+        $(impl Counter for $t {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const NAME: &'static str = stringify!($t);
+
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_add(self, rhs)
             }
-            h.remove(&line.0)
+
+            fn checked_shl(self, rhs: u32) -> Option<Self> {
+                // The std version only checks that the shift is less than the width.
+                <$t>::checked_shl(self, rhs).filter(|v| v >> rhs == self)
+            }
+        })*
+    };
+}
+
+impl_counter!(u32, u64, u128);
+
+/// Lines and cards are 1-based.
+#[derive(Debug, PartialEq)]
+enum CardErr {
+    Malformed {
+        line: usize,
+        msg: String,
+    },
+    DuplicateNumber {
+        line: usize,
+        number: u32,
+    },
+    DuplicateId {
+        line: usize,
+        id: usize,
+        first_line: usize,
+    },
+    NonSequential {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// `what` names the overflowing value.
+    Overflow {
+        card: usize,
+        what: &'static str,
+        counter: &'static str,
+    },
+}
+
+impl fmt::Display for CardErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardErr::Malformed { line, msg } => write!(f, "line {}: malformed card: {}", line, msg),
+            CardErr::DuplicateNumber { line, number } => {
+                write!(f, "line {}: winning number {} is repeated", line, number)
+            }
+            CardErr::DuplicateId {
+                line,
+                id,
+                first_line,
+            } => write!(
+                f,
+                "line {}: card {} is already defined at line {}",
+                line, id, first_line
+            ),
+            CardErr::NonSequential {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected card {}, got {}",
+                line, expected, found
+            ),
+            CardErr::Overflow {
+                card,
+                what,
+                counter,
+            } => write!(f, "card {}: {} overflow {}", card, what, counter),
+        }
+    }
+}
+
+fn overflow<T: Counter>(card: usize, what: &'static str) -> CardErr {
+    CardErr::Overflow {
+        card: card + 1,
+        what,
+        counter: T::NAME,
+    }
+}
+
+fn points<T: Counter>(wins: u32) -> Option<T> {
+    match wins {
+        wins @ 1.. => T::ONE.checked_shl(wins - 1),
+        _ => Some(T::ZERO),
+    }
+}
+
+/// What a single card ended up with after all copies were won.
+#[derive(Debug, Clone, PartialEq)]
+struct CardReport<T> {
+    card: usize,
+    matches: u32,
+    points: T,
+    copies: T,
+    /// Copies of the following cards won by all copies of this one.
+    generated: T,
+}
+
+fn cascade<T: Counter>(data: &str) -> Result<Vec<CardReport<T>>, CardErr> {
+    let matches = count_wins(&parse_cards(data)?)?;
+
+    let mut copies = vec![T::ONE; matches.len()];
+    let mut reports = Vec::with_capacity(matches.len());
+    for (i, &wins) in matches.iter().enumerate() {
+        let won = i + 1..cmp::min(i + 1 + wins as usize, copies.len());
+        let mut generated = T::ZERO;
+        for k in won {
+            copies[k] = copies[k]
+                .checked_add(copies[i])
+                .ok_or_else(|| overflow::<T>(k, "copies"))?;
+            generated = generated
+                .checked_add(copies[i])
+                .ok_or_else(|| overflow::<T>(i, "generated copies"))?;
+        }
+
+        reports.push(CardReport {
+            card: i + 1,
+            matches: wins,
+            points: points(wins).ok_or_else(|| overflow::<T>(i, "points"))?,
+            copies: copies[i],
+            generated,
+        });
+    }
+
+    Ok(reports)
+}
+
+fn solve1<T: Counter>(data: &str) -> Result<T, CardErr> {
+    count_wins(&parse_cards(data)?)?
+        .into_iter()
+        .enumerate()
+        .try_fold(T::ZERO, |sum, (i, wins)| {
+            points::<T>(wins)
+                .and_then(|p| sum.checked_add(p))
+                .ok_or_else(|| overflow::<T>(i, "points"))
         })
-        .sum()
+}
+
+fn solve2<T: Counter>(data: &str) -> Result<T, CardErr> {
+    cascade::<T>(data)?.iter().try_fold(T::ZERO, |sum, r| {
+        sum.checked_add(r.copies)
+            .ok_or_else(|| overflow::<T>(r.card - 1, "total copies"))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReportFormat {
+    Table,
+    Csv,
+}
+
+/// How many cards are listed in the summary of the table report.
+const TOP_CARDS: usize = 5;
+
+fn print_report<T: Counter>(reports: &[CardReport<T>], format: ReportFormat) {
+    let header = ["card", "matches", "points", "copies", "generated"];
+    let rows = reports.iter().map(|r| {
+        [
+            r.card.to_string(),
+            r.matches.to_string(),
+            r.points.to_string(),
+            r.copies.to_string(),
+            r.generated.to_string(),
+        ]
+    });
+
+    if format == ReportFormat::Csv {
+        println!("{}", header.join(","));
+        for row in rows {
+            println!("{}", row.join(","));
+        }
+        return;
+    }
+
+    println!("{}", header.map(|h| format!("{:>9}", h)).join(" |"));
+    for row in rows {
+        println!("{}", row.map(|v| format!("{:>9}", v)).join(" |"));
+    }
+
+    let mut top: Vec<&CardReport<T>> = reports.iter().collect();
+    top.sort_by(|a, b| b.generated.cmp(&a.generated).then(a.card.cmp(&b.card)));
+    println!("Cards that generated the most copies:");
+    for r in top.iter().take(TOP_CARDS) {
+        println!("Card {}: {} copies", r.card, r.generated);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    path: Option<String>,
+    report: Option<ReportFormat>,
+    counter: CounterType,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum CounterType {
+    U32,
+    #[default]
+    U64,
+    U128,
+}
+
+impl Options {
+    /// Usage: `day04 [path] [--report table|csv] [--counter u32|u64|u128]`.
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut opts = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--report" => {
+                    opts.report = match args.next().as_deref() {
+                        Some("table") => Some(ReportFormat::Table),
+                        Some("csv") => Some(ReportFormat::Csv),
+                        v => return Err(format!("--report expects table or csv, got {:?}", v)),
+                    }
+                }
+                "--counter" => {
+                    opts.counter = match args.next().as_deref() {
+                        Some("u32") => CounterType::U32,
+                        Some("u64") => CounterType::U64,
+                        Some("u128") => CounterType::U128,
+                        v => {
+                            return Err(format!("--counter expects u32, u64 or u128, got {:?}", v))
+                        }
+                    }
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if opts.path.is_some() => return Err(format!("unexpected argument {}", arg)),
+                _ => opts.path = Some(arg),
+            }
+        }
+
+        Ok(opts)
+    }
 }
 
 pub fn main() {
-    let given = args()
-        .nth(1)
-        .and_then(|path| fs::read_to_string(path).ok())
-        .unwrap_or("".to_string());
+    let opts = Options::from_args(args().skip(1)).expect("Can't parse arguments");
+    let given = opts.path.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't read {}: {}", path, e))
+    });
 
     let builtin = include_str!("../data.txt");
-    let data = if given.is_empty() { builtin } else { &given };
+    let data = given.as_deref().unwrap_or(builtin);
+
+    match opts.counter {
+        CounterType::U32 => run::<u32>(data, &opts),
+        CounterType::U64 => run::<u64>(data, &opts),
+        CounterType::U128 => run::<u128>(data, &opts),
+    }
+}
+
+fn run<T: Counter>(data: &str, opts: &Options) {
+    if let Some(format) = opts.report {
+        let reports = cascade::<T>(data).unwrap_or_else(|e| panic!("{}", e));
+        print_report(&reports, format);
+    }
 
-    let result1 = solve1(&data);
+    let result1 = solve1::<T>(data).unwrap_or_else(|e| panic!("{}", e));
     dbg!(result1);
 
     // low 5458681
-    let result2 = solve2(&data);
+    let result2 = solve2::<T>(data).unwrap_or_else(|e| panic!("{}", e));
     dbg!(result2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
+
+    #[test]
+    fn test_example() {
+        assert_eq!(Ok(13), solve1::<u32>(EXAMPLE));
+        assert_eq!(Ok(30), solve2::<u32>(EXAMPLE));
+    }
+
+    #[test]
+    fn test_cascade() {
+        let reports = cascade::<u32>(EXAMPLE).unwrap();
+        let copies: Vec<u32> = reports.iter().map(|r| r.copies).collect();
+        assert_eq!(vec![1, 2, 4, 8, 14, 1], copies);
+        let generated: Vec<u32> = reports.iter().map(|r| r.generated).collect();
+        assert_eq!(vec![4, 4, 8, 8, 0, 0], generated);
+        assert_eq!(
+            CardReport {
+                card: 3,
+                matches: 2,
+                points: 2,
+                copies: 4,
+                generated: 8
+            },
+            reports[2]
+        );
+    }
+
+    /// `cards` cards where every card wins `wins` matches.
+    fn generate(cards: usize, wins: usize) -> String {
+        let nums: Vec<String> = (1..=wins).map(|n| n.to_string()).collect();
+        let nums = nums.join(" ");
+        (1..=cards)
+            .map(|i| format!("Card {}: {} | {}\n", i, nums, nums))
+            .collect()
+    }
+
+    #[test]
+    fn test_points_overflow() {
+        let data = generate(1, 32);
+        assert_eq!(Ok(1 << 31), solve1::<u32>(&data));
+        let data = generate(1, 33);
+        assert_eq!(Err(overflow::<u32>(0, "points")), solve1::<u32>(&data));
+        assert_eq!(Ok(1 << 32), solve1::<u64>(&data));
+        assert_eq!(Ok(1 << 99), solve1::<u128>(&generate(1, 100)));
+        assert_eq!(
+            "card 1: points overflow u32",
+            overflow::<u32>(0, "points").to_string()
+        );
+    }
+
+    #[test]
+    fn test_copies_overflow() {
+        // Every card wins a copy of all the following cards, so card `i` ends up with 2^i copies.
+        let data = generate(32, 31);
+        assert_eq!(Ok(u32::MAX), solve2::<u32>(&data));
+        let data = generate(33, 32);
+        assert!(matches!(
+            solve2::<u32>(&data),
+            Err(CardErr::Overflow { card: 33, .. })
+        ));
+        assert_eq!(Ok((1 << 33) - 1), solve2::<u64>(&data));
+        assert_eq!(Ok(u128::MAX), solve2::<u128>(&generate(128, 127)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |data: &str| parse_cards(data).unwrap_err();
+
+        assert_eq!(
+            CardErr::Malformed {
+                line: 2,
+                msg: "missing '|' between number lists".to_string()
+            },
+            err("Card 1: 1 | 1\nCard 2: 1 2")
+        );
+        assert!(matches!(
+            err("Card x: 1 | 1"),
+            CardErr::Malformed { line: 1, .. }
+        ));
+        assert!(matches!(
+            err("Game 1: 1 | 1"),
+            CardErr::Malformed { line: 1, .. }
+        ));
+        assert!(matches!(
+            err("Card 1: 1 a | 1"),
+            CardErr::Malformed { line: 1, .. }
+        ));
+        assert_eq!(
+            CardErr::DuplicateNumber { line: 1, number: 7 },
+            err("Card 1: 7 3 7 | 1")
+        );
+        assert_eq!(
+            CardErr::DuplicateId {
+                line: 4,
+                id: 2,
+                first_line: 2
+            },
+            err("Card 1: 1 | 1\nCard 2: 1 | 1\n\nCard 2: 1 | 1")
+        );
+        assert_eq!(
+            CardErr::NonSequential {
+                line: 2,
+                expected: 2,
+                found: 3
+            },
+            err("Card 1: 1 | 1\nCard 3: 1 | 1")
+        );
+        assert_eq!(
+            "line 2: expected card 2, got 3",
+            err("Card 1: 1 | 1\nCard 3: 1 | 1").to_string()
+        );
+        assert_eq!(
+            6,
+            parse_cards(&(EXAMPLE.to_string() + "\n\n")).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_args() {
+        let parse = |args: &[&str]| Options::from_args(args.iter().map(|a| a.to_string()));
+        let opts = parse(&["cards.txt", "--report", "csv"]).unwrap();
+        assert_eq!(Some("cards.txt".to_string()), opts.path);
+        assert!(opts.report.is_some());
+
+        assert_eq!(
+            Err("unknown option --reprot".to_string()),
+            parse(&["cards.txt", "--reprot", "table"]).map(|_| ())
+        );
+        assert_eq!(
+            Err("unexpected argument b.txt".to_string()),
+            parse(&["a.txt", "b.txt"]).map(|_| ())
+        );
+    }
+}
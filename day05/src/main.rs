@@ -1,94 +1,719 @@
-use rayon::prelude::*;
-use std::{env::args, fs, str::Lines};
+mod piecewise;
+
+use piecewise::Piecewise;
+use std::{
+    cmp,
+    collections::{HashMap, VecDeque},
+    env::args,
+    fs,
+    ops::Range,
+    str::FromStr,
+};
 use utils::dbg;
 
-fn parse(it: &mut Lines) -> Vec<(u32, u32, u32)> {
-    let mut parsed: Vec<(u32, u32, u32)> = it
-        .skip_while(|&line| line.is_empty() || !line.as_bytes()[0].is_ascii_digit())
-        .take_while(|&line| !line.is_empty())
-        .map(|line| {
-            let mut it = line
-                .split(|x| x == ' ')
-                .map(|x| x.parse::<u32>().expect("parse"));
+/// Every category value. Entries are validated so that `src + len` and
+/// `dst + len` fit, the mapping code relies on that and doesn't check again.
+type Value = u64;
+
+/// A `from-to-to map:` section, `entries` are `(src, dst, len)` sorted by `src`
+/// and their source ranges don't overlap.
+#[derive(Debug, PartialEq)]
+struct Map {
+    from: String,
+    to: String,
+    entries: Vec<(Value, Value, Value)>,
+}
 
-            let dst = it.next().expect("1");
-            let src = it.next().expect("2");
-            let len = it.next().expect("3");
+#[derive(Debug, PartialEq)]
+struct Almanac {
+    seeds: Vec<Value>,
+    maps: Vec<Map>,
+}
 
-            (src, dst, len)
+fn parse_nums(line: &str, line_no: usize) -> Result<Vec<Value>, String> {
+    line.split_whitespace()
+        .map(|x| {
+            x.parse::<Value>()
+                .map_err(|e| format!("line {}: {} for {}", line_no, e, x))
         })
-        .collect();
-    parsed.sort();
-    parsed
+        .collect()
+}
+
+impl FromStr for Almanac {
+    type Err = String;
+
+    /// Sections may come in any order and there can be any number of them.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut seeds = None;
+        let mut maps: Vec<Map> = Vec::new();
+        // Line numbers of the entries of every map, in input order.
+        let mut lines: Vec<Vec<usize>> = Vec::new();
+        for (i, line) in s.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("seeds:") {
+                seeds = Some(parse_nums(rest, i)?);
+            } else if let Some(header) = line.strip_suffix(" map:") {
+                let (from, to) = header
+                    .split_once("-to-")
+                    .ok_or_else(|| format!("line {}: expected 'a-to-b map:'", i))?;
+                maps.push(Map {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    entries: Vec::new(),
+                });
+                lines.push(Vec::new());
+            } else {
+                let map = maps
+                    .last_mut()
+                    .ok_or_else(|| format!("line {}: numbers outside of a map", i))?;
+                match parse_nums(line, i)?[..] {
+                    [dst, src, len] => {
+                        if src.checked_add(len).is_none() || dst.checked_add(len).is_none() {
+                            return Err(format!(
+                                "line {}: range doesn't fit into {}",
+                                i,
+                                Value::MAX
+                            ));
+                        }
+                        map.entries.push((src, dst, len));
+                        lines.last_mut().unwrap().push(i);
+                    }
+                    _ => return Err(format!("line {}: expected 'dst src len'", i)),
+                }
+            }
+        }
+        for (map, lines) in maps.iter_mut().zip(lines) {
+            let mut entries: Vec<_> = map.entries.drain(..).zip(lines).collect();
+            entries.sort();
+            for pair in entries.windows(2) {
+                let ((src, _, len), line) = pair[0];
+                let ((next, _, _), next_line) = pair[1];
+                if src + len > next {
+                    return Err(format!("line {}: overlaps line {}", next_line, line));
+                }
+            }
+            map.entries = entries.into_iter().map(|(entry, _)| entry).collect();
+        }
+
+        Ok(Almanac {
+            seeds: seeds.ok_or("missing 'seeds:' line")?,
+            maps,
+        })
+    }
+}
+
+impl Almanac {
+    /// The shortest sequence of maps leading from one category to another.
+    fn chain(&self, from: &str, to: &str) -> Result<Vec<&Map>, String> {
+        let mut prev: HashMap<&str, &Map> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(category) = queue.pop_front() {
+            if category == to {
+                let mut chain = Vec::new();
+                let mut category = to;
+                while category != from {
+                    let map = prev[category];
+                    chain.push(map);
+                    category = &map.from;
+                }
+                chain.reverse();
+                return Ok(chain);
+            }
+            for map in self.maps.iter().filter(|m| m.from == category) {
+                if map.to != from && !prev.contains_key(map.to.as_str()) {
+                    prev.insert(&map.to, map);
+                    queue.push_back(&map.to);
+                }
+            }
+        }
+
+        Err(format!("no maps lead from {} to {}", from, to))
+    }
+}
+
+/// How the `seeds:` line is read.
+type SeedRanges = fn(&[Value]) -> Result<Vec<Range<Value>>, String>;
+
+fn seed_range(start: Value, len: Value) -> Result<Range<Value>, String> {
+    match start.checked_add(len) {
+        Some(end) => Ok(start..end),
+        None => Err(format!(
+            "seeds {} {} don't fit into {}",
+            start,
+            len,
+            Value::MAX
+        )),
+    }
 }
 
-fn parse_seed1(data: &str) -> Vec<u32> {
-    data.split(' ')
-        .filter_map(|v| v.parse::<u32>().ok())
-        .collect::<Vec<u32>>()
+fn seed_ranges1(seeds: &[Value]) -> Result<Vec<Range<Value>>, String> {
+    seeds.iter().map(|&v| seed_range(v, 1)).collect()
 }
 
-fn parse_seed2(data: &str) -> Vec<u32> {
-    let parsed = parse_seed1(data);
-    parsed
+fn seed_ranges2(seeds: &[Value]) -> Result<Vec<Range<Value>>, String> {
+    seeds
         .chunks_exact(2)
-        .map(|xs| xs[0]..(xs[0] + xs[1]))
-        .flatten()
+        .map(|xs| seed_range(xs[0], xs[1]))
         .collect()
 }
 
-fn solve(data: &str, parse_seed: fn(&str) -> Vec<u32>) -> u32 {
-    let mut it = data.lines();
-    let seeds = parse_seed(it.next().expect("seed"));
+/// Splits `range` on the boundaries of the sorted `param` and pushes every mapped
+/// piece into `out`. Values outside of all entries are mapped to themselves.
+fn map_range(param: &[(Value, Value, Value)], range: Range<Value>, out: &mut Vec<Range<Value>>) {
+    let mut start = range.start;
+    let first = param.partition_point(|&(src, _, len)| src + len <= start);
+    for &(src, dst, len) in param[first..].iter().take_while(|p| p.0 < range.end) {
+        if start < src {
+            out.push(start..src);
+            start = src;
+        }
+        let end = cmp::min(range.end, src + len);
+        out.push(dst + (start - src)..dst + (end - src));
+        start = end;
+    }
+    if start < range.end {
+        out.push(start..range.end);
+    }
+}
+
+/// `(dst, src, len)` of the sorted `param` sorted by `dst`, the dst ranges may overlap.
+fn invert(param: &[(Value, Value, Value)]) -> Vec<(Value, Value, Value)> {
+    let mut inverse: Vec<(Value, Value, Value)> = param
+        .iter()
+        .map(|&(src, dst, len)| (dst, src, len))
+        .collect();
+    inverse.sort();
+    inverse
+}
 
-    let params = vec![
-        parse(&mut it),
-        parse(&mut it),
-        parse(&mut it),
-        parse(&mut it),
-        parse(&mut it),
-        parse(&mut it),
-        parse(&mut it),
-    ];
+/// Pushes every range of values that the sorted `param` maps into `range` to `out`.
+/// `inverse` is `invert(param)`.
+fn unmap_range(
+    param: &[(Value, Value, Value)],
+    inverse: &[(Value, Value, Value)],
+    range: Range<Value>,
+    out: &mut Vec<Range<Value>>,
+) {
+    // Values outside of all entries are their own preimage.
+    let mut start = range.start;
+    let first = param.partition_point(|&(src, _, len)| src + len <= start);
+    for &(src, _, len) in param[first..].iter().take_while(|p| p.0 < range.end) {
+        if start < src {
+            out.push(start..src);
+        }
+        start = cmp::max(start, cmp::min(range.end, src + len));
+    }
+    if start < range.end {
+        out.push(start..range.end);
+    }
 
-    seeds
-        .par_iter()
-        .map(|&seed| {
-            let mut param_value = seed;
-            for param in &params {
-                param_value = match param.binary_search_by(|p| p.0.cmp(&param_value)) {
-                    Ok(i) => param[i].1,
-                    Err(i) if i == 0 => param_value,
-                    Err(i) => {
-                        let (src, dst, len) = param[i - 1];
-                        let diff = param_value - src;
-                        if len < diff {
-                            param_value
-                        } else {
-                            dst + diff
+    for &(dst, src, len) in inverse.iter().take_while(|p| p.0 < range.end) {
+        let (lo, hi) = (cmp::max(range.start, dst), cmp::min(range.end, dst + len));
+        if lo < hi {
+            out.push(src + (lo - dst)..src + (hi - dst));
+        }
+    }
+}
+
+/// Walks the chain backwards and returns all values of category `from`
+/// that end up in `ranges` of category `to`.
+fn preimages(
+    almanac: &Almanac,
+    from: &str,
+    to: &str,
+    ranges: Vec<Range<Value>>,
+) -> Result<Vec<Range<Value>>, String> {
+    let chain = almanac.chain(from, to)?;
+    let inverses: Vec<_> = chain.iter().map(|map| invert(&map.entries)).collect();
+    Ok(unmap_chain(&chain, &inverses, ranges))
+}
+
+fn unmap_chain(
+    chain: &[&Map],
+    inverses: &[Vec<(Value, Value, Value)>],
+    mut ranges: Vec<Range<Value>>,
+) -> Vec<Range<Value>> {
+    for (map, inverse) in chain.iter().zip(inverses).rev() {
+        let mut unmapped = Vec::with_capacity(ranges.len());
+        for range in ranges.into_iter().filter(|r| !r.is_empty()) {
+            unmap_range(&map.entries, inverse, range, &mut unmapped);
+        }
+        ranges = unmapped;
+    }
+    ranges
+}
+
+/// Same as `solve`, but scans values of category `to` upward until one of them
+/// comes from a seed. Windows grow exponentially and the hit is narrowed down by bisection.
+fn solve_inverse(
+    almanac: &Almanac,
+    seed_ranges: SeedRanges,
+    from: &str,
+    to: &str,
+) -> Result<Value, String> {
+    let seeds = seed_ranges(&almanac.seeds)?;
+    let chain = almanac.chain(from, to)?;
+    let inverses: Vec<_> = chain.iter().map(|map| invert(&map.entries)).collect();
+    let hits = |range: Range<Value>| {
+        unmap_chain(&chain, &inverses, vec![range]).iter().any(|r| {
+            seeds
+                .iter()
+                .any(|s| cmp::max(r.start, s.start) < cmp::min(r.end, s.end))
+        })
+    };
+
+    let (mut lo, mut step) = (0 as Value, 1 as Value);
+    let mut hi = loop {
+        let hi = lo.saturating_add(step);
+        if hits(lo..hi) {
+            break hi;
+        }
+        if hi == Value::MAX {
+            return Err("no seeds".to_string());
+        }
+        (lo, step) = (hi, step.saturating_mul(2));
+    };
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if hits(lo..mid) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    Ok(lo)
+}
+
+/// Parses `a` or the half-open `a..b`.
+fn parse_range(s: &str) -> Result<Range<Value>, String> {
+    let num = |v: &str| {
+        v.trim()
+            .parse::<Value>()
+            .map_err(|e| format!("{} for {}", e, v))
+    };
+    match s.split_once("..") {
+        Some((a, b)) => Ok(num(a)?..num(b)?),
+        None => {
+            let v = num(s)?;
+            Ok(v..v.checked_add(1).ok_or("value is too big")?)
+        }
+    }
+}
+
+/// The lowest value in category `to` for the seed values taken as category `from`.
+fn solve(
+    almanac: &Almanac,
+    seed_ranges: SeedRanges,
+    from: &str,
+    to: &str,
+) -> Result<Value, String> {
+    let mut ranges = seed_ranges(&almanac.seeds)?;
+    for map in almanac.chain(from, to)? {
+        let mut mapped = Vec::with_capacity(ranges.len());
+        for range in ranges.into_iter().filter(|r| !r.is_empty()) {
+            map_range(&map.entries, range, &mut mapped);
+        }
+        ranges = mapped;
+    }
+
+    ranges
+        .iter()
+        .map(|r| r.start)
+        .min()
+        .ok_or_else(|| "no seeds".to_string())
+}
+
+/// Same as `solve`, but composes the chain into a single mapping first.
+fn solve_composed(
+    almanac: &Almanac,
+    seed_ranges: SeedRanges,
+    from: &str,
+    to: &str,
+) -> Result<Value, String> {
+    let mapping = Piecewise::compose(almanac.chain(from, to)?);
+    seed_ranges(&almanac.seeds)?
+        .into_iter()
+        .filter_map(|r| mapping.min_over(r))
+        .min()
+        .ok_or_else(|| "no seeds".to_string())
+}
+
+type Solver = fn(&Almanac, SeedRanges, &str, &str) -> Result<Value, String>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MappingFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug)]
+struct Options {
+    path: Option<String>,
+    from: String,
+    to: String,
+    solver: Solver,
+    map: Option<Value>,
+    unmap: Option<Range<Value>>,
+    mapping: Option<MappingFormat>,
+}
+
+impl Options {
+    /// Usage: `day05 [path] [--from seed] [--to location] [--solver ranges|inverse|composed]
+    /// [--map 79] [--unmap 46|40..50] [--mapping text|json]`.
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut opts = Options {
+            path: None,
+            from: "seed".to_string(),
+            to: "location".to_string(),
+            solver: solve,
+            map: None,
+            unmap: None,
+            mapping: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--from" => opts.from = args.next().ok_or("--from expects a category")?,
+                "--to" => opts.to = args.next().ok_or("--to expects a category")?,
+                "--solver" => {
+                    opts.solver = match args.next().as_deref() {
+                        Some("ranges") => solve,
+                        Some("inverse") => solve_inverse,
+                        Some("composed") => solve_composed,
+                        v => {
+                            return Err(format!(
+                                "--solver expects ranges, inverse or composed, got {:?}",
+                                v
+                            ))
                         }
                     }
-                };
+                }
+                "--mapping" => {
+                    opts.mapping = match args.next().as_deref() {
+                        Some("text") => Some(MappingFormat::Text),
+                        Some("json") => Some(MappingFormat::Json),
+                        v => return Err(format!("--mapping expects text or json, got {:?}", v)),
+                    }
+                }
+                "--map" => {
+                    let value = args.next().ok_or("--map expects a value")?;
+                    opts.map = Some(value.parse().map_err(|e| format!("{} for {}", e, value))?)
+                }
+                "--unmap" => {
+                    opts.unmap = Some(parse_range(
+                        &args.next().ok_or("--unmap expects a value or a range")?,
+                    )?)
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if opts.path.is_some() => return Err(format!("unexpected argument {}", arg)),
+                _ => opts.path = Some(arg),
             }
+        }
 
-            param_value
-        })
-        .min()
-        .expect("min")
+        Ok(opts)
+    }
 }
 
 pub fn main() {
-    let given = args()
-        .nth(1)
-        .and_then(|path| fs::read_to_string(path).ok())
-        .unwrap_or("".to_string());
+    let opts = Options::from_args(args().skip(1)).expect("Can't parse arguments");
+    let given = opts.path.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't read {}: {}", path, e))
+    });
 
     let builtin = include_str!("../data.txt");
-    let data = if given.is_empty() { builtin } else { &given };
+    let data = given.as_deref().unwrap_or(builtin);
+
+    let almanac = Almanac::from_str(data).expect("Can't parse almanac");
+
+    if let Some(range) = &opts.unmap {
+        let ranges =
+            preimages(&almanac, &opts.from, &opts.to, vec![range.clone()]).expect("Can't unmap");
+        println!(
+            "{} {:?} comes from {} {:?}",
+            opts.to, range, opts.from, ranges
+        );
+    }
 
-    let result1 = solve(&data, parse_seed1);
+    if opts.map.is_some() || opts.mapping.is_some() {
+        let mapping = Piecewise::compose(almanac.chain(&opts.from, &opts.to).expect("Can't map"));
+        if let Some(value) = opts.map {
+            let mapped = mapping.apply(value);
+            println!("{} {} maps to {} {}", opts.from, value, opts.to, mapped);
+        }
+        match opts.mapping {
+            Some(MappingFormat::Text) => print!("{}", mapping),
+            Some(MappingFormat::Json) => println!("{}", mapping.to_json()),
+            None => {}
+        }
+    }
+
+    let solve = opts.solver;
+    let result1 = solve(&almanac, seed_ranges1, &opts.from, &opts.to).expect("solve1");
     dbg!(result1);
 
-    let result2 = solve(&data, parse_seed2);
+    let result2 = solve(&almanac, seed_ranges2, &opts.from, &opts.to).expect("solve2");
     dbg!(result2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub const EXAMPLE: &str = "seeds: 79 14 55 13
+
+seed-to-soil map:
+50 98 2
+52 50 48
+
+soil-to-fertilizer map:
+0 15 37
+37 52 2
+39 0 15
+
+fertilizer-to-water map:
+49 53 8
+0 11 42
+42 0 7
+57 7 4
+
+water-to-light map:
+88 18 7
+18 25 70
+
+light-to-temperature map:
+45 77 23
+81 45 19
+68 64 13
+
+temperature-to-humidity map:
+0 69 1
+1 0 69
+
+humidity-to-location map:
+60 56 37
+56 93 4
+";
+
+    #[test]
+    fn test_example() {
+        let almanac = Almanac::from_str(EXAMPLE).unwrap();
+        assert_eq!(Ok(35), solve(&almanac, seed_ranges1, "seed", "location"));
+        assert_eq!(Ok(46), solve(&almanac, seed_ranges2, "seed", "location"));
+    }
+
+    #[test]
+    fn test_chain() {
+        // Shuffle the sections, the chain only depends on the headers.
+        let mut sections: Vec<&str> = EXAMPLE.split("\n\n").collect();
+        sections[1..].reverse();
+        let almanac = Almanac::from_str(&sections.join("\n\n")).unwrap();
+        assert_eq!(Ok(35), solve(&almanac, seed_ranges1, "seed", "location"));
+
+        let chain: Vec<&str> = almanac
+            .chain("soil", "humidity")
+            .unwrap()
+            .iter()
+            .map(|m| m.to.as_str())
+            .collect();
+        assert_eq!(
+            vec!["fertilizer", "water", "light", "temperature", "humidity"],
+            chain
+        );
+        assert!(almanac.chain("seed", "seed").unwrap().is_empty());
+        assert!(almanac.chain("location", "seed").is_err());
+        assert!(almanac.chain("seed", "weather").is_err());
+
+        // Seed 79 is soil 81, fertilizer 81 and water 81.
+        assert_eq!(
+            Ok(81),
+            solve(&almanac, |_| seed_ranges1(&[79]), "seed", "water")
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Almanac::from_str("seed-to-soil map:\n1 2 3").is_err());
+        assert!(Almanac::from_str("seeds: 1\n1 2 3").is_err());
+        assert!(Almanac::from_str("seeds: 1\nseed-soil map:").is_err());
+        assert!(Almanac::from_str("seeds: 1\nseed-to-soil map:\n1 2").is_err());
+        assert!(Almanac::from_str("seeds: x").is_err());
+
+        assert_eq!(
+            Err("line 4: overlaps line 3".to_string()),
+            Almanac::from_str("seeds: 1\na-to-b map:\n0 10 5\n100 12 3")
+        );
+        assert!(Almanac::from_str("seeds: 1\na-to-b map:\n0 10 5\n100 15 3\n0 10 0").is_ok());
+        assert_eq!(
+            Err("line 3: range doesn't fit into 18446744073709551615".to_string()),
+            Almanac::from_str("seeds: 1\na-to-b map:\n0 18446744073709551615 1")
+        );
+
+        let almanac = Almanac::from_str("seeds: 18446744073709551614 2").unwrap();
+        assert_eq!(Ok(2), solve(&almanac, seed_ranges1, "seed", "seed"));
+        assert!(solve(&almanac, seed_ranges2, "seed", "seed").is_err());
+    }
+
+    /// A value is mapped by the entry with `src <= value < src + len`,
+    /// the value right after a range is not.
+    fn naive(map: &Map, value: Value) -> Value {
+        map.entries
+            .iter()
+            .find(|&&(src, _, len)| src <= value && value - src < len)
+            .map_or(value, |&(src, dst, _)| dst + (value - src))
+    }
+
+    #[test]
+    fn test_random_almanacs() {
+        let mut state: u64 = 0x2023_1205;
+        let mut rand = |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+
+        for round in 0..200 {
+            // Half of the almanacs live right below the largest value.
+            let base = if round % 2 == 0 { 0 } else { Value::MAX - 256 };
+            let mut text = String::from("seeds:");
+            for _ in 0..3 {
+                text += &format!(" {} {}", base + rand(220), rand(20));
+            }
+            for (from, to) in [("a", "b"), ("b", "c"), ("c", "d")] {
+                text += &format!("\n\n{}-to-{} map:", from, to);
+                let mut lines = Vec::new();
+                let mut src = base + rand(10);
+                while src < base + 200 {
+                    let len = 1 + rand(20);
+                    lines.push(format!("{} {} {}", base + rand(220), src, len));
+                    src += len + rand(10);
+                }
+                while !lines.is_empty() {
+                    let line = lines.swap_remove(rand(lines.len() as u64) as usize);
+                    text += &format!("\n{}", line);
+                }
+            }
+            let almanac = Almanac::from_str(&text).unwrap();
+            let chain = almanac.chain("a", "d").unwrap();
+            let composed = Piecewise::compose(chain.iter().copied());
+
+            let expected = |value: Value| chain.iter().fold(value, |v, map| naive(map, v));
+            for value in base..base + 250 {
+                let mut range = value..value + 1;
+                for map in &chain {
+                    let mut mapped = Vec::new();
+                    map_range(&map.entries, range, &mut mapped);
+                    assert_eq!(1, mapped.len(), "{}", text);
+                    range = mapped.pop().unwrap();
+                }
+                assert_eq!(expected(value), range.start, "{} {}", value, text);
+                assert_eq!(expected(value), composed.apply(value), "{} {}", value, text);
+            }
+
+            for seed_ranges in [seed_ranges1 as SeedRanges, seed_ranges2] {
+                let lowest = seed_ranges(&almanac.seeds)
+                    .unwrap()
+                    .into_iter()
+                    .flatten()
+                    .map(expected)
+                    .min()
+                    .ok_or_else(|| "no seeds".to_string());
+                for solver in [solve as Solver, solve_inverse, solve_composed] {
+                    assert_eq!(lowest, solver(&almanac, seed_ranges, "a", "d"), "{}", text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_map_range() {
+        let param = vec![(10, 100, 5), (20, 0, 5)];
+        let mut out = Vec::new();
+        map_range(&param, 0..30, &mut out);
+        assert_eq!(vec![0..10, 100..105, 15..20, 0..5, 25..30], out);
+
+        out.clear();
+        map_range(&param, 12..22, &mut out);
+        assert_eq!(vec![102..105, 15..20, 0..2], out);
+
+        // The value just past an entry is not mapped.
+        out.clear();
+        map_range(&param, 15..16, &mut out);
+        assert_eq!(vec![15..16], out);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let almanac = Almanac::from_str(EXAMPLE).unwrap();
+        assert_eq!(
+            Ok(35),
+            solve_inverse(&almanac, seed_ranges1, "seed", "location")
+        );
+        assert_eq!(
+            Ok(46),
+            solve_inverse(&almanac, seed_ranges2, "seed", "location")
+        );
+        assert_eq!(
+            Ok(35),
+            solve_composed(&almanac, seed_ranges1, "seed", "location")
+        );
+        assert_eq!(
+            Ok(46),
+            solve_composed(&almanac, seed_ranges2, "seed", "location")
+        );
+
+        // Every value in 0..120 is in the preimage of exactly the location it maps to.
+        let chain = almanac.chain("seed", "location").unwrap();
+        for location in 0..120 {
+            let range = location..location + 1;
+            let seeds = preimages(&almanac, "seed", "location", vec![range]).unwrap();
+            for seed in 0..120 {
+                let mut ranges = Vec::new();
+                let mut value = seed..seed + 1;
+                for map in &chain {
+                    ranges.clear();
+                    map_range(&map.entries, value, &mut ranges);
+                    value = ranges[0].clone();
+                }
+                let in_preimage = seeds.iter().any(|r| r.contains(&seed));
+                assert_eq!(
+                    value.start == location,
+                    in_preimage,
+                    "{} {}",
+                    seed,
+                    location
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_unmap_range() {
+        // Both 5 and 10 map to 5: the entry moves 10 to 5 and 5 isn't mapped.
+        let param = vec![(10, 5, 5)];
+        let mut out = Vec::new();
+        unmap_range(&param, &invert(&param), 0..20, &mut out);
+        assert_eq!(vec![0..10, 15..20, 10..15], out);
+        assert_eq!(Ok(40..50), parse_range("40..50"));
+        assert_eq!(Ok(46..47), parse_range("46"));
+    }
+
+    #[test]
+    fn test_args() {
+        let parse = |args: &[&str]| Options::from_args(args.iter().map(|a| a.to_string()));
+        let opts = parse(&["almanac.txt", "--from", "soil"]).unwrap();
+        assert_eq!(Some("almanac.txt".to_string()), opts.path);
+        assert_eq!("soil", opts.from);
+
+        assert_eq!(
+            Err("unknown option --solve".to_string()),
+            parse(&["almanac.txt", "--solve", "ranges"]).map(|_| ())
+        );
+        assert_eq!(
+            Err("unexpected argument b.txt".to_string()),
+            parse(&["a.txt", "b.txt"]).map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{Map, Value};
+use serde::Serialize;
+use std::{cmp, fmt, ops::Range};
+
+/// The end of the last segment doesn't fit into a `Value`.
+const END: u128 = 1 << Value::BITS;
+
+/// Values in `start..end` are moved by `offset`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Segment {
+    pub(crate) start: u128,
+    pub(crate) end: u128,
+    pub(crate) offset: i128,
+}
+
+/// A mapping of all `Value` values made of sorted segments that cover the whole
+/// domain without gaps, neighbours never share an offset.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Piecewise {
+    pub(crate) segments: Vec<Segment>,
+}
+
+impl Piecewise {
+    pub(crate) fn identity() -> Self {
+        Piecewise {
+            segments: vec![Segment {
+                start: 0,
+                end: END,
+                offset: 0,
+            }],
+        }
+    }
+
+    /// Values covered by no entry are mapped to themselves.
+    /// For overlapping entries the one with the lowest `src` wins.
+    pub(crate) fn from_map(map: &Map) -> Self {
+        let mut segments = Vec::with_capacity(map.entries.len() * 2 + 1);
+        let mut start = 0;
+        for &(src, dst, len) in &map.entries {
+            let (src, end) = (src as u128, src as u128 + len as u128);
+            if end <= start {
+                continue;
+            }
+            if start < src {
+                segments.push(Segment {
+                    start,
+                    end: src,
+                    offset: 0,
+                });
+            }
+            segments.push(Segment {
+                start: cmp::max(start, src),
+                end,
+                offset: dst as i128 - src as i128,
+            });
+            start = end;
+        }
+        if start < END {
+            segments.push(Segment {
+                start,
+                end: END,
+                offset: 0,
+            });
+        }
+
+        Self::normalized(segments)
+    }
+
+    /// Maps by `self` first and by `next` after that.
+    pub(crate) fn then(&self, next: &Piecewise) -> Self {
+        let mut segments = Vec::with_capacity(self.segments.len() + next.segments.len());
+        for s in &self.segments {
+            let (lo, hi) = (s.start as i128 + s.offset, s.end as i128 + s.offset);
+            let first = next.segments.partition_point(|n| n.end as i128 <= lo);
+            for n in next.segments[first..]
+                .iter()
+                .take_while(|n| (n.start as i128) < hi)
+            {
+                segments.push(Segment {
+                    start: (cmp::max(lo, n.start as i128) - s.offset) as u128,
+                    end: (cmp::min(hi, n.end as i128) - s.offset) as u128,
+                    offset: s.offset + n.offset,
+                });
+            }
+        }
+
+        Self::normalized(segments)
+    }
+
+    /// Composes the maps in order.
+    pub(crate) fn compose<'a>(maps: impl IntoIterator<Item = &'a Map>) -> Self {
+        maps.into_iter()
+            .fold(Self::identity(), |acc, map| acc.then(&Self::from_map(map)))
+    }
+
+    fn normalized(segments: Vec<Segment>) -> Self {
+        let mut result: Vec<Segment> = Vec::with_capacity(segments.len());
+        for s in segments.into_iter().filter(|s| s.start < s.end) {
+            match result.last_mut() {
+                Some(last) if last.offset == s.offset => last.end = s.end,
+                _ => result.push(s),
+            }
+        }
+        Piecewise { segments: result }
+    }
+
+    fn find(&self, value: u128) -> usize {
+        self.segments.partition_point(|s| s.end <= value)
+    }
+
+    pub(crate) fn apply(&self, value: Value) -> Value {
+        let s = &self.segments[self.find(value as u128)];
+        (value as i128 + s.offset) as Value
+    }
+
+    /// The lowest value `range` is mapped to, `None` for an empty range.
+    pub(crate) fn min_over(&self, range: Range<Value>) -> Option<Value> {
+        let (start, end) = (range.start as u128, range.end as u128);
+        if range.is_empty() {
+            return None;
+        }
+        self.segments[self.find(start)..]
+            .iter()
+            .take_while(|s| s.start < end)
+            .map(|s| (cmp::max(start, s.start) as i128 + s.offset) as Value)
+            .min()
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Piecewise is always serializable")
+    }
+}
+
+impl fmt::Display for Piecewise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in &self.segments {
+            writeln!(f, "{}..{} {:+}", s.start, s.end, s.offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::EXAMPLE, Almanac};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_compose() {
+        let almanac = Almanac::from_str(EXAMPLE).unwrap();
+        let chain = almanac.chain("seed", "location").unwrap();
+        let composed = Piecewise::compose(chain.iter().copied());
+
+        assert_eq!(
+            vec![82, 43, 86, 35],
+            almanac
+                .seeds
+                .iter()
+                .map(|&s| composed.apply(s))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(46), composed.min_over(82..83));
+        assert_eq!(None, composed.min_over(5..5));
+
+        for pair in composed.segments.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert_ne!(pair[0].offset, pair[1].offset);
+        }
+        assert_eq!(0, composed.segments[0].start);
+        assert_eq!(END, composed.segments.last().unwrap().end);
+    }
+
+    #[test]
+    fn test_from_map() {
+        let map = Map {
+            from: "a".to_string(),
+            to: "b".to_string(),
+            entries: vec![(10, 100, 5), (15, 105, 5), (Value::MAX - 2, 0, 2)],
+        };
+        let piecewise = Piecewise::from_map(&map);
+        assert_eq!(
+            "0..10 +0\n10..20 +90\n20..18446744073709551613 +0\n\
+             18446744073709551613..18446744073709551615 -18446744073709551613\n\
+             18446744073709551615..18446744073709551616 +0\n",
+            piecewise.to_string()
+        );
+        assert_eq!(1, piecewise.apply(Value::MAX - 1));
+        assert_eq!(Value::MAX, piecewise.apply(Value::MAX));
+        assert_eq!(piecewise, piecewise.then(&Piecewise::identity()));
+
+        let json: serde_json::Value = serde_json::from_str(&piecewise.to_json()).unwrap();
+        assert_eq!(90, json["segments"][1]["offset"]);
+    }
+}
@@ -1,61 +1,436 @@
-use std::{cmp::Ordering, env::args, fs};
+use std::{array, cmp, env::args, fs, ops::RangeInclusive};
 use utils::dbg;
 
-fn calc_wins(time_to_go: u64, best_distance: u64) -> u32 {
-    let d = ((time_to_go.pow(2) - 4 * best_distance) as f64).sqrt();
-    let x1 = (time_to_go as f64 - d) / 2.0;
-    let x2 = (time_to_go as f64 + d) / 2.0;
-    let (min, max) = match x1.partial_cmp(&x2) {
-        Some(Ordering::Less) => (x1, x2),
-        _ => (x2, x1),
-    };
+/// How a boat moves: every ms the button is held adds `accel` to its speed, up to
+/// `max_speed`, and it starts moving `delay` ms after the button is released.
+/// The puzzle itself is `Physics::default()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Physics {
+    accel: u64,
+    max_speed: Option<u64>,
+    delay: u64,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Physics {
+            accel: 1,
+            max_speed: None,
+            delay: 0,
+        }
+    }
+}
+
+impl Physics {
+    fn speed(&self, hold: u64) -> u128 {
+        let speed = self.accel as u128 * hold as u128;
+        match self.max_speed {
+            Some(max) => cmp::min(speed, max as u128),
+            None => speed,
+        }
+    }
+
+    fn distance(&self, time_to_go: u64, hold: u64) -> u128 {
+        let moving = (time_to_go as u128).saturating_sub(hold as u128 + self.delay as u128);
+        self.speed(hold) * moving
+    }
+
+    /// The first hold time at which the speed reaches `max_speed`.
+    fn capped_from(&self) -> Option<u64> {
+        let accel = self.accel;
+        self.max_speed
+            .filter(|_| accel > 0)
+            .map(|max| max.div_ceil(accel))
+    }
+}
 
-    (max.floor() - min.ceil()) as u32 + 1
+/// Hold times `h` with `h * (t - h) > d`, `None` when there are none.
+///
+/// `h` lies strictly between the roots `(t ± sqrt(t² - 4d)) / 2`. The root is taken
+/// with an integer sqrt, so the first win computed from it is off by at most one
+/// and is corrected by stepping. Wins are symmetric around `t / 2`.
+fn parabola_wins(t: u64, d: u64) -> Option<RangeInclusive<u64>> {
+    let wide = t as u128;
+    let discriminant = (wide * wide).checked_sub(4 * d as u128)?;
+
+    let puzzle = Physics::default();
+    let mut first = ((wide - discriminant.isqrt()) / 2) as u64;
+    while first <= t / 2 && puzzle.distance(t, first) <= d as u128 {
+        first += 1;
+    }
+
+    (first <= t / 2).then(|| first..=t - first)
 }
 
-fn parse<'a>(line: &'a str) -> impl Iterator<Item = u32> + 'a {
-    line.split(' ').filter_map(|x| x.parse::<u32>().ok())
+/// Hold times that beat `best_distance`, `None` when the race can't be won.
+///
+/// The distance is the product of a speed that grows with the hold time and a
+/// moving time that shrinks with it, both concave, so it rises and then falls and
+/// the wins always form a single interval. It's found piecewise: before the speed
+/// cap `accel * h * (m - h) > d` is the puzzle with `d / accel`, after it
+/// `max_speed * (m - h) > d` is linear.
+fn calc_wins(
+    physics: &Physics,
+    time_to_go: u64,
+    best_distance: u64,
+) -> Option<RangeInclusive<u64>> {
+    if physics.accel == 0 {
+        return None;
+    }
+    let moving = time_to_go.checked_sub(physics.delay)?;
+    let capped_from = physics.capped_from().unwrap_or(u64::MAX);
+
+    let before = capped_from
+        .checked_sub(1)
+        .and_then(|last| {
+            parabola_wins(moving, best_distance / physics.accel)
+                .map(|wins| *wins.start()..=cmp::min(*wins.end(), last))
+        })
+        .filter(|wins| !wins.is_empty());
+    let after = physics
+        .max_speed
+        .filter(|&max| max > 0)
+        .and_then(|max| moving.checked_sub(best_distance / max)?.checked_sub(1))
+        .map(|last| capped_from..=last)
+        .filter(|wins| !wins.is_empty());
+
+    match (before, after) {
+        (Some(a), Some(b)) => Some(*a.start()..=*b.end()),
+        (a, b) => a.or(b),
+    }
 }
 
-fn solve1(data: &str) -> u32 {
+fn count(wins: Option<RangeInclusive<u64>>) -> u64 {
+    wins.map_or(0, |wins| wins.end() - wins.start() + 1)
+}
+
+fn parse(line: &str) -> impl Iterator<Item = u64> + '_ {
+    line.split(' ').filter_map(|x| x.parse::<u64>().ok())
+}
+
+/// `(time, record)` of every race.
+fn races1(data: &str) -> Vec<(u64, u64)> {
     let mut it = data.lines();
     let time = parse(it.next().expect("time"));
     let distance = parse(it.next().expect("distance"));
 
-    time.zip(distance)
-        .map(|(time_to_go, best_distance)| calc_wins(time_to_go as u64, best_distance as u64))
+    time.zip(distance).collect()
+}
+
+fn solve1(data: &str, physics: &Physics) -> u64 {
+    races1(data)
+        .into_iter()
+        .map(|(time_to_go, best_distance)| count(calc_wins(physics, time_to_go, best_distance)))
         .product()
 }
 
-fn parse2<'a>(line: &'a str) -> u64 {
+fn parse2(line: &str) -> u64 {
     line.chars()
-        .into_iter()
         .filter(|&v| v.is_ascii_digit())
         .collect::<String>()
         .parse()
         .expect("parse2")
 }
 
-fn solve2(data: &str) -> u32 {
+/// The single race with the spaces between digits ignored.
+fn race2(data: &str) -> (u64, u64) {
     let mut it = data.lines();
     let time_to_go = parse2(it.next().expect("time"));
     let best_distance = parse2(it.next().expect("distance"));
 
-    calc_wins(time_to_go, best_distance)
+    (time_to_go, best_distance)
+}
+
+fn solve2(data: &str, physics: &Physics) -> u64 {
+    let (time_to_go, best_distance) = race2(data);
+    count(calc_wins(physics, time_to_go, best_distance))
+}
+
+#[derive(Debug, PartialEq)]
+struct RaceReport {
+    time: u64,
+    record: u64,
+    wins: Option<RangeInclusive<u64>>,
+    /// The hold time that goes the farthest, the shortest one on ties.
+    best_hold: u64,
+    best_distance: u128,
+    /// How far the best hold beats the record by, negative when it can't.
+    margin: i128,
+}
+
+fn report(physics: &Physics, time: u64, record: u64) -> RaceReport {
+    // The distance peaks either in the middle of the moving time or where the
+    // speed reaches the cap, the best hold is next to one of them.
+    let moving = time.saturating_sub(physics.delay);
+    let mut candidates = vec![moving / 2, moving.div_ceil(2)];
+    if let Some(capped_from) = physics.capped_from() {
+        candidates.extend([capped_from.saturating_sub(1), capped_from]);
+    }
+    let best_hold = candidates
+        .into_iter()
+        .map(|hold| cmp::min(hold, time))
+        .max_by_key(|&hold| (physics.distance(time, hold), cmp::Reverse(hold)))
+        .expect("at least two candidates");
+    let best_distance = physics.distance(time, best_hold);
+
+    RaceReport {
+        time,
+        record,
+        wins: calc_wins(physics, time, record),
+        best_hold,
+        best_distance,
+        margin: best_distance as i128 - record as i128,
+    }
+}
+
+fn print_reports(title: &str, reports: &[RaceReport]) {
+    let header = [
+        "race",
+        "time",
+        "record",
+        "wins",
+        "count",
+        "best hold",
+        "distance",
+        "margin",
+    ];
+    let rows: Vec<[String; 8]> = reports
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            [
+                (i + 1).to_string(),
+                r.time.to_string(),
+                r.record.to_string(),
+                r.wins
+                    .as_ref()
+                    .map_or("-".to_string(), |w| format!("{}..={}", w.start(), w.end())),
+                count(r.wins.clone()).to_string(),
+                r.best_hold.to_string(),
+                r.best_distance.to_string(),
+                format!("{:+}", r.margin),
+            ]
+        })
+        .collect();
+    let widths: [usize; 8] = array::from_fn(|j| {
+        rows.iter()
+            .map(|row| row[j].len())
+            .fold(header[j].len(), cmp::max)
+    });
+
+    println!("{}:", title);
+    println!(
+        "{}",
+        array::from_fn::<_, 8, _>(|j| format!("{:>w$}", header[j], w = widths[j])).join(" |")
+    );
+    for row in rows {
+        println!(
+            "{}",
+            array::from_fn::<_, 8, _>(|j| format!("{:>w$}", row[j], w = widths[j])).join(" |")
+        );
+    }
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    path: Option<String>,
+    physics: Physics,
+    report: bool,
+}
+
+fn next_number(name: &str, args: &mut impl Iterator<Item = String>) -> Result<u64, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} expects a number", name))?;
+    value
+        .parse()
+        .map_err(|e| format!("{} for {} {}", e, name, value))
+}
+
+impl Options {
+    /// Usage: `day06 [path] [--accel 1] [--max-speed N] [--delay 0] [--report]`.
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut opts = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--accel" => opts.physics.accel = next_number(&arg, &mut args)?,
+                "--max-speed" => opts.physics.max_speed = Some(next_number(&arg, &mut args)?),
+                "--delay" => opts.physics.delay = next_number(&arg, &mut args)?,
+                "--report" => opts.report = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if opts.path.is_some() => return Err(format!("unexpected argument {}", arg)),
+                _ => opts.path = Some(arg),
+            }
+        }
+
+        Ok(opts)
+    }
 }
 
 pub fn main() {
-    let given = args()
-        .nth(1)
-        .and_then(|path| fs::read_to_string(path).ok())
-        .unwrap_or("".to_string());
+    let opts = Options::from_args(args().skip(1)).expect("Can't parse arguments");
+    let given = opts.path.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't read {}: {}", path, e))
+    });
 
     let builtin = include_str!("../data.txt");
-    let data = if given.is_empty() { builtin } else { &given };
+    let data = given.as_deref().unwrap_or(builtin);
+
+    if opts.report {
+        let reports: Vec<RaceReport> = races1(data)
+            .into_iter()
+            .map(|(time, record)| report(&opts.physics, time, record))
+            .collect();
+        print_reports("part 1", &reports);
+        let (time, record) = race2(data);
+        print_reports("part 2", &[report(&opts.physics, time, record)]);
+    }
 
-    let result1 = solve1(&data);
+    let result1 = solve1(data, &opts.physics);
     dbg!(result1);
 
-    let result2 = solve2(&data);
+    let result2 = solve2(data, &opts.physics);
     dbg!(result2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Time:      7  15   30
+Distance:  9  40  200
+";
+
+    #[test]
+    fn test_example() {
+        let physics = Physics::default();
+        assert_eq!(288, solve1(EXAMPLE, &physics));
+        assert_eq!(71503, solve2(EXAMPLE, &physics));
+        assert_eq!(Some(2..=5), calc_wins(&physics, 7, 9));
+    }
+
+    #[test]
+    fn test_report() {
+        let physics = Physics::default();
+        assert_eq!(
+            RaceReport {
+                time: 7,
+                record: 9,
+                wins: Some(2..=5),
+                best_hold: 3,
+                best_distance: 12,
+                margin: 3,
+            },
+            report(&physics, 7, 9)
+        );
+        assert_eq!(Some(14..=71516), report(&physics, 71530, 940200).wins);
+
+        let lost = report(&physics, 4, 4);
+        assert_eq!((None, 2, 0), (lost.wins, lost.best_hold, lost.margin));
+
+        let capped = Physics {
+            max_speed: Some(3),
+            ..physics
+        };
+        let r = report(&capped, 10, 16);
+        assert_eq!((3, 21, 5), (r.best_hold, r.best_distance, r.margin));
+    }
+
+    #[test]
+    fn test_brute_force() {
+        for time_to_go in 0..60 {
+            for best_distance in 0..time_to_go * time_to_go / 4 + 3 {
+                let expected = (0..=time_to_go)
+                    .filter(|&h| h * (time_to_go - h) > best_distance)
+                    .count() as u64;
+                assert_eq!(
+                    expected,
+                    count(calc_wins(&Physics::default(), time_to_go, best_distance)),
+                    "{} {}",
+                    time_to_go,
+                    best_distance
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_large() {
+        // 2^32 ms overflows `t²` in `u64`.
+        let wins = |t, d| count(calc_wins(&Physics::default(), t, d));
+        let t = 1 << 32;
+        assert_eq!(t - 1, wins(t, 0));
+        assert_eq!(1, wins(t, (t / 2) * (t / 2) - 1));
+        assert_eq!(0, wins(t, (t / 2) * (t / 2)));
+        assert_eq!(u64::MAX - 3, wins(u64::MAX, u64::MAX));
+        assert_eq!(u64::MAX - 1, wins(u64::MAX, 0));
+    }
+
+    #[test]
+    fn test_physics() {
+        let mut state: u32 = 6;
+        let mut rand = |n: u32| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            ((state >> 16) % n) as u64
+        };
+
+        for _ in 0..3000 {
+            let physics = Physics {
+                accel: rand(4),
+                max_speed: [None, Some(rand(30))][rand(2) as usize],
+                delay: rand(5),
+            };
+            let time_to_go = rand(40);
+            let best_distance = rand(300);
+            let wins: Vec<u64> = (0..=time_to_go)
+                .filter(|&h| physics.distance(time_to_go, h) > best_distance as u128)
+                .collect();
+            let expected = wins.first().map(|&first| first..=*wins.last().unwrap());
+            let farthest = (0..=time_to_go)
+                .map(|h| physics.distance(time_to_go, h))
+                .max();
+            let r = report(&physics, time_to_go, best_distance);
+            assert_eq!(
+                farthest,
+                Some(r.best_distance),
+                "{:?} {}",
+                physics,
+                time_to_go
+            );
+            assert_eq!(r.best_distance, physics.distance(time_to_go, r.best_hold));
+            assert_eq!(expected.clone().map_or(0, |w| w.count()), wins.len());
+            assert_eq!(
+                expected,
+                calc_wins(&physics, time_to_go, best_distance),
+                "{:?} {} {}",
+                physics,
+                time_to_go,
+                best_distance
+            );
+        }
+
+        let capped = Physics {
+            max_speed: Some(3),
+            ..Physics::default()
+        };
+        assert_eq!(3 * 5, capped.distance(10, 5));
+        assert_eq!(Some(3..=4), calc_wins(&capped, 10, 16));
+    }
+
+    #[test]
+    fn test_args() {
+        let parse = |args: &[&str]| Options::from_args(args.iter().map(|a| a.to_string()));
+        let opts = parse(&["races.txt", "--report"]).unwrap();
+        assert_eq!(Some("races.txt".to_string()), opts.path);
+        assert!(opts.report);
+
+        assert_eq!(
+            Err("unknown option --acel".to_string()),
+            parse(&["races.txt", "--acel", "2"]).map(|_| ())
+        );
+        assert_eq!(
+            Err("unexpected argument b.txt".to_string()),
+            parse(&["a.txt", "b.txt"]).map(|_| ())
+        );
+    }
+}
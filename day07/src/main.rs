@@ -1,156 +1,906 @@
-use std::{env::args, fs};
+use std::{
+    cmp::{self, Ordering},
+    env::args,
+    fmt, fs,
+    marker::PhantomData,
+};
 use utils::dbg;
 
-struct Cards([char; 13]);
-const CARDS1_VALUES: Cards = Cards([
-    '2', '3', '4', '5', '6', '7', '8', '9', 'T', 'J', 'Q', 'K', 'A',
-]);
-const CARDS2_VALUES: Cards = Cards([
-    'J', '2', '3', '4', '5', '6', '7', '8', '9', 'T', 'Q', 'K', 'A',
-]);
-
-impl Cards {
-    fn value(&self, card: char) -> u8 {
-        self.0
-            .iter()
-            .enumerate()
-            .find(|v| *v.1 == card)
-            .expect("card number")
-            .0 as u8
+const CARDS: &str = "23456789TJQKA";
+const HAND_SIZE: usize = 5;
+
+/// Sizes of the groups of equal cards from the largest, hands of the same size
+/// rank by comparing them in order: `[5]` beats `[4, 1]` beats `[3, 2]` and so on,
+/// `[7]` beats `[5, 2]` in 7-card hands.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct HandType(Vec<u8>);
+
+impl HandType {
+    fn new(card_count: &[u8]) -> Self {
+        Self::with_wild(card_count.iter().copied(), 0)
+    }
+
+    /// `wild` cards join the largest group.
+    fn with_wild(card_count: impl Iterator<Item = u8>, wild: u8) -> Self {
+        let mut groups: Vec<u8> = card_count.filter(|&c| c > 0).collect();
+        groups.sort_by(|a, b| b.cmp(a));
+        match groups.first_mut() {
+            Some(first) => *first += wild,
+            None if wild > 0 => groups.push(wild),
+            None => {}
+        }
+        HandType(groups)
     }
 }
 
-#[derive(Clone, Copy)]
-enum HandType {
-    Empty = 0,
-    HighCard,
-    OnePair,
-    TwoPair,
-    ThreeKind,
-    FullHouse,
-    FourKind,
-    FiveKind,
+impl fmt::Display for HandType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WORDS: [&str; 11] = [
+            "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+        ];
+        let word = |n: u8| {
+            WORDS
+                .get(n as usize)
+                .map_or(n.to_string(), |w| w.to_string())
+        };
+        let pairs = self.0.iter().filter(|&&g| g == 2).count() as u8;
+        let mut names: Vec<String> = self
+            .0
+            .iter()
+            .filter(|&&g| g > 2)
+            .map(|&g| format!("{} of a kind", word(g)))
+            .collect();
+        match (&self.0[..], pairs) {
+            ([3, 2], _) => return write!(f, "full house"),
+            (_, 0) if names.is_empty() => return write!(f, "high card"),
+            (_, 0) => {}
+            _ => names.push(format!("{} pair", word(pairs))),
+        }
+        write!(f, "{}", names.join(" + "))
+    }
 }
 
-fn hand_type_1(card_count: [u8; 13]) -> HandType {
-    let mut hand_type = HandType::Empty;
-    for count in card_count {
-        hand_type = match (hand_type, count) {
-            (HandType::Empty, 1) => HandType::HighCard,
+fn hand_type_1(card_count: &[u8]) -> HandType {
+    HandType::new(card_count)
+}
 
-            (HandType::Empty | HandType::HighCard, 5) => HandType::FiveKind,
-            (HandType::Empty | HandType::HighCard, 4) => HandType::FourKind,
-            (HandType::Empty | HandType::HighCard, 3) => HandType::ThreeKind,
-            (HandType::Empty | HandType::HighCard, 2) => HandType::OnePair,
+/// `card_count[0]` are jokers, they join the largest group of the other cards.
+fn hand_type_2(card_count: &[u8]) -> HandType {
+    HandType::with_wild(card_count[1..].iter().copied(), card_count[0])
+}
 
-            (HandType::OnePair, 3) => HandType::FullHouse,
-            (HandType::ThreeKind, 2) => HandType::FullHouse,
-            (HandType::OnePair, 2) => HandType::TwoPair,
+trait GameRule {
+    /// Cards from the weakest to the strongest.
+    fn alphabet(&self) -> &[char];
+    fn hand_size(&self) -> usize;
+    /// `card_count[i]` is the number of `alphabet()[i]` cards in a hand.
+    fn hand_type(&self, card_count: &[u8]) -> HandType;
 
-            _ => hand_type,
-        };
+    /// `None` for a card outside of the alphabet.
+    fn value(&self, card: char) -> Option<u8> {
+        self.alphabet()
+            .iter()
+            .position(|&c| c == card)
+            .map(|v| v as u8)
     }
-    hand_type
 }
 
-fn hand_type_2(card_count: [u8; 13]) -> HandType {
-    let joker = card_count[0];
-    let mut jokerless = card_count;
-    jokerless[0] = 0;
+/// Every card counts as itself.
+struct Game1 {
+    alphabet: Vec<char>,
+    hand_size: usize,
+}
 
-    let mut hand_type = hand_type_1(jokerless);
-    for _ in 0..joker {
-        hand_type = match hand_type {
-            HandType::Empty => HandType::HighCard,
-            HandType::HighCard => HandType::OnePair,
-            HandType::OnePair => HandType::ThreeKind,
-            HandType::TwoPair => HandType::FullHouse,
-            HandType::ThreeKind => HandType::FourKind,
-            HandType::FullHouse => HandType::FourKind,
-            HandType::FourKind => HandType::FiveKind,
-            _ => unreachable!(),
+impl Game1 {
+    fn new(alphabet: &str, hand_size: usize) -> Self {
+        Game1 {
+            alphabet: alphabet.chars().collect(),
+            hand_size,
         }
     }
-
-    hand_type
 }
 
-trait GameRule {
-    fn value(card: char) -> u8;
-    fn score(count: [u8; 13]) -> u8;
+impl Default for Game1 {
+    fn default() -> Self {
+        Game1::new(CARDS, HAND_SIZE)
+    }
 }
 
-struct Game1;
 impl GameRule for Game1 {
-    fn value(card: char) -> u8 {
-        CARDS1_VALUES.value(card)
+    fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+    fn hand_size(&self) -> usize {
+        self.hand_size
     }
-    fn score(count: [u8; 13]) -> u8 {
-        hand_type_1(count) as u8
+    fn hand_type(&self, card_count: &[u8]) -> HandType {
+        hand_type_1(card_count)
+    }
+}
+
+/// `joker` becomes the weakest card and counts as whatever makes the best hand.
+struct Game2 {
+    alphabet: Vec<char>,
+    hand_size: usize,
+}
+
+impl Game2 {
+    fn new(alphabet: &str, hand_size: usize, joker: char) -> Self {
+        let alphabet = if alphabet.contains(joker) {
+            [joker]
+                .into_iter()
+                .chain(alphabet.chars().filter(|&c| c != joker))
+                .collect()
+        } else {
+            // Without a joker nothing is wild, the placeholder never matches a card.
+            ['\0'].into_iter().chain(alphabet.chars()).collect()
+        };
+        Game2 {
+            alphabet,
+            hand_size,
+        }
+    }
+}
+
+impl Default for Game2 {
+    fn default() -> Self {
+        Game2::new(CARDS, HAND_SIZE, 'J')
     }
 }
 
-struct Game2;
 impl GameRule for Game2 {
-    fn value(card: char) -> u8 {
-        CARDS2_VALUES.value(card)
+    fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+    fn hand_size(&self) -> usize {
+        self.hand_size
     }
-    fn score(count: [u8; 13]) -> u8 {
-        hand_type_2(count) as u8
+    fn hand_type(&self, card_count: &[u8]) -> HandType {
+        hand_type_2(card_count)
     }
 }
 
-fn to_hand<T: GameRule>(s: &str) -> u32 {
-    if s.len() != 5 {
-        unreachable!()
+/// `card_count[i]` for every wild `i` join the largest group of the other cards.
+fn hand_type_wild(card_count: &[u8], wild: &[bool]) -> HandType {
+    let jokers = card_count
+        .iter()
+        .zip(wild)
+        .filter(|p| *p.1)
+        .map(|p| p.0)
+        .sum();
+    let tame = card_count.iter().zip(wild).filter(|p| !*p.1).map(|p| *p.0);
+    HandType::with_wild(tame, jokers)
+}
+
+/// Any of `wild` counts as whatever makes the best hand, wild cards become the
+/// weakest ones and keep their order among themselves.
+struct Wild {
+    alphabet: Vec<char>,
+    hand_size: usize,
+    wild: Vec<bool>,
+}
+
+impl Wild {
+    fn new(alphabet: &str, hand_size: usize, wild: &[char]) -> Self {
+        let (wilds, tame): (Vec<char>, Vec<char>) =
+            alphabet.chars().partition(|c| wild.contains(c));
+        Wild {
+            wild: wilds
+                .iter()
+                .map(|_| true)
+                .chain(tame.iter().map(|_| false))
+                .collect(),
+            alphabet: wilds.into_iter().chain(tame).collect(),
+            hand_size,
+        }
     }
+}
 
-    let mut cards_count: [u8; 13] = [0; 13];
-    let hand = s
-        .chars()
-        .rev()
-        .enumerate()
-        .map(|c| {
-            let card = T::value(c.1);
-            cards_count[card as usize] += 1;
-            (card as u32) << c.0 * 4
-        })
-        .fold(0, |i, v| i | v);
+impl GameRule for Wild {
+    fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+    fn hand_size(&self) -> usize {
+        self.hand_size
+    }
+    fn hand_type(&self, card_count: &[u8]) -> HandType {
+        hand_type_wild(card_count, &self.wild)
+    }
+}
+
+/// Same as `Wild`, but wild cards keep their rank when breaking ties.
+struct RankedWild {
+    alphabet: Vec<char>,
+    hand_size: usize,
+    wild: Vec<bool>,
+}
+
+impl RankedWild {
+    fn new(alphabet: &str, hand_size: usize, wild: &[char]) -> Self {
+        RankedWild {
+            alphabet: alphabet.chars().collect(),
+            hand_size,
+            wild: alphabet.chars().map(|c| wild.contains(&c)).collect(),
+        }
+    }
+}
+
+impl GameRule for RankedWild {
+    fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+    fn hand_size(&self) -> usize {
+        self.hand_size
+    }
+    fn hand_type(&self, card_count: &[u8]) -> HandType {
+        hand_type_wild(card_count, &self.wild)
+    }
+}
+
+/// An input may name its wild cards on a `wild: JQ` line, the line isn't a hand.
+const WILD_PREFIX: &str = "wild:";
+
+fn input_wild(data: &str) -> Option<Vec<char>> {
+    data.lines()
+        .find_map(|line| line.strip_prefix(WILD_PREFIX))
+        .map(|cards| cards.chars().filter(|c| !c.is_whitespace()).collect())
+}
+
+#[derive(Debug, PartialEq)]
+enum HandErr {
+    Size {
+        expected: usize,
+        found: usize,
+    },
+    /// `col` is 1-based.
+    Card {
+        col: usize,
+        card: char,
+    },
+}
+
+impl fmt::Display for HandErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandErr::Size { expected, found } => {
+                write!(f, "expected {} cards, got {}", expected, found)
+            }
+            HandErr::Card { col, card } => write!(f, "invalid card {:?} at column {}", card, col),
+        }
+    }
+}
+
+/// `line` is 1-based.
+#[derive(Debug, PartialEq)]
+enum InputErr {
+    Malformed {
+        line: usize,
+        msg: String,
+    },
+    Hand {
+        line: usize,
+        err: HandErr,
+    },
+    Tie {
+        line: usize,
+        cards: String,
+        first_line: usize,
+    },
+    Overflow,
+}
 
-    let hand_type = (T::score(cards_count) as u32) << 5 * 4;
+impl fmt::Display for InputErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputErr::Malformed { line, msg } => {
+                write!(f, "line {}: malformed hand: {}", line, msg)
+            }
+            InputErr::Hand { line, err } => write!(f, "line {}: {}", line, err),
+            InputErr::Tie {
+                line,
+                cards,
+                first_line,
+            } => write!(
+                f,
+                "line {}: hand {} is already dealt at line {}",
+                line, cards, first_line
+            ),
+            InputErr::Overflow => write!(f, "total winnings overflow u64"),
+        }
+    }
+}
 
-    hand_type | hand
+/// What happens to hands with identical cards, they compare equal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Ties {
+    /// The input is invalid.
+    Reject,
+    /// Every hand of a tie gets the lowest rank of the group, the next hand
+    /// continues after the group as if the hands were distinct.
+    Share,
+    /// The hand that comes first in the input ranks lower.
+    InputOrder,
 }
 
-fn solve<T: GameRule>(data: &str) -> u32 {
-    let mut hand_bid: Vec<(u32, u32)> = data
-        .lines()
-        .map(|line| {
-            let (hand, bid) = line.split_once(' ').expect("split");
-            (to_hand::<T>(hand), bid.parse::<u32>().expect("bid"))
+/// A hand ranked by the rules of `R`.
+struct Hand<'r, R: GameRule> {
+    cards: String,
+    hand_type: HandType,
+    /// Card values in the order of `cards`.
+    values: Vec<u8>,
+    /// The group sizes followed by the card values packed into an integer, so
+    /// hands compare in one step. `None` when they don't fit.
+    key: Option<u128>,
+    /// Only hands of the same rules compare.
+    rule: PhantomData<&'r R>,
+}
+
+impl<'r, R: GameRule> Hand<'r, R> {
+    fn new(rule: &'r R, cards: &str) -> Result<Self, HandErr> {
+        let found = cards.chars().count();
+        if found != rule.hand_size() {
+            return Err(HandErr::Size {
+                expected: rule.hand_size(),
+                found,
+            });
+        }
+
+        let mut card_count = vec![0; rule.alphabet().len()];
+        let values: Vec<u8> = cards
+            .chars()
+            .enumerate()
+            .map(|(i, card)| {
+                let value = rule.value(card).ok_or(HandErr::Card { col: i + 1, card })?;
+                card_count[value as usize] += 1;
+                Ok(value)
+            })
+            .collect::<Result<_, _>>()?;
+        let hand_type = rule.hand_type(&card_count);
+
+        // Group sizes padded with zeros to the hand size keep their order.
+        let bits = |max: usize| usize::BITS - max.leading_zeros();
+        let (group_bits, card_bits) = (bits(found), bits(rule.alphabet().len() - 1));
+        let key = (found as u32 * (group_bits + card_bits) <= u128::BITS).then(|| {
+            let groups = (0..found).map(|i| *hand_type.0.get(i).unwrap_or(&0));
+            let key = groups.fold(0, |key, g| (key << group_bits) | g as u128);
+            values
+                .iter()
+                .fold(key, |key, &v| (key << card_bits) | v as u128)
+        });
+
+        Ok(Hand {
+            cards: cards.to_string(),
+            hand_type,
+            values,
+            key,
+            rule: PhantomData,
         })
-        .collect();
+    }
+}
+
+impl<R: GameRule> PartialEq for Hand<'_, R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<R: GameRule> Eq for Hand<'_, R> {}
 
-    hand_bid.sort();
-    hand_bid
+impl<R: GameRule> PartialOrd for Hand<'_, R> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<R: GameRule> Ord for Hand<'_, R> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.key, other.key) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => (&self.hand_type, &self.values).cmp(&(&other.hand_type, &other.values)),
+        }
+    }
+}
+
+impl<R: GameRule> fmt::Display for Hand<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.cards, self.hand_type)
+    }
+}
+
+impl<R: GameRule> fmt::Debug for Hand<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Hand({:?}, {:?}, {} cards)",
+            self.cards,
+            self.hand_type,
+            self.values.len()
+        )
+    }
+}
+
+struct Ranked<'r, R: GameRule> {
+    hand: Hand<'r, R>,
+    bid: u64,
+    rank: u64,
+    line: usize,
+}
+
+fn parse_line<'r, R: GameRule>(
+    rule: &'r R,
+    line: &str,
+    line_no: usize,
+) -> Result<(Hand<'r, R>, u64), InputErr> {
+    let malformed = |msg: String| InputErr::Malformed { line: line_no, msg };
+    let (cards, bid) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [cards, bid] => (cards, bid),
+        _ => return Err(malformed("expected 'cards bid'".to_string())),
+    };
+    let hand = Hand::new(rule, cards).map_err(|err| InputErr::Hand { line: line_no, err })?;
+    let bid = bid
+        .parse::<u64>()
+        .map_err(|e| malformed(format!("{} for bid {}", e, bid)))?;
+
+    Ok((hand, bid))
+}
+
+/// Hands with their bids and ranks from the weakest to the strongest.
+fn ranking<'r, R: GameRule>(
+    rule: &'r R,
+    data: &str,
+    ties: Ties,
+) -> Result<Vec<Ranked<'r, R>>, InputErr> {
+    let mut ranked = Vec::new();
+    for (i, line) in data.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+        if line.is_empty() || line.starts_with(WILD_PREFIX) {
+            continue;
+        }
+        let (hand, bid) = parse_line(rule, line, i)?;
+        ranked.push(Ranked {
+            hand,
+            bid,
+            rank: 0,
+            line: i,
+        });
+    }
+
+    // Stable, so tied hands stay in input order.
+    ranked.sort_by(|a, b| a.hand.cmp(&b.hand));
+    for i in 0..ranked.len() {
+        let tied = i > 0 && ranked[i - 1].hand == ranked[i].hand;
+        ranked[i].rank = match (tied, ties) {
+            (true, Ties::Reject) => {
+                return Err(InputErr::Tie {
+                    line: ranked[i].line,
+                    cards: ranked[i].hand.cards.clone(),
+                    first_line: ranked[i - 1].line,
+                })
+            }
+            (true, Ties::Share) => ranked[i - 1].rank,
+            _ => i as u64 + 1,
+        };
+    }
+
+    Ok(ranked)
+}
+
+fn solve<R: GameRule>(rule: &R, data: &str, ties: Ties) -> Result<u64, InputErr> {
+    ranking(rule, data, ties)?
         .iter()
-        .enumerate()
-        .map(|(i, (_, b))| (i as u32 + 1) * b)
-        .sum()
+        .try_fold(0u64, |total, r| {
+            r.rank.checked_mul(r.bid)?.checked_add(total)
+        })
+        .ok_or(InputErr::Overflow)
+}
+
+fn print_ranking<R: GameRule>(title: &str, ranked: &[Ranked<R>]) {
+    let width = ranked.iter().map(|r| r.hand.cards.len()).fold(4, cmp::max);
+    println!("{}:", title);
+    println!(
+        "{:>6} | {:<width$} | {:<15} | {:>6} | {:>6}",
+        "rank", "hand", "type", "bid", "line"
+    );
+    for r in ranked {
+        println!(
+            "{:>6} | {:<width$} | {:<15} | {:>6} | {:>6}",
+            r.rank,
+            r.hand.cards,
+            r.hand.hand_type.to_string(),
+            r.bid,
+            r.line
+        );
+    }
+}
+
+fn run<R: GameRule>(title: &str, rule: &R, data: &str, opts: &Options) -> u64 {
+    if opts.ranking {
+        let ranked = ranking(rule, data, opts.ties).unwrap_or_else(|e| panic!("{}", e));
+        print_ranking(title, &ranked);
+    }
+    solve(rule, data, opts.ties).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rule2 {
+    Joker,
+    Wild,
+    RankedWild,
+}
+
+#[derive(Debug)]
+struct Options {
+    path: Option<String>,
+    ranking: bool,
+    alphabet: String,
+    hand_size: usize,
+    joker: char,
+    rule2: Rule2,
+    /// `None` reads the wild cards from the input.
+    wild: Option<Vec<char>>,
+    ties: Ties,
+}
+
+impl Options {
+    fn wild_cards(&self, data: &str) -> Result<Vec<char>, String> {
+        let wild = match &self.wild {
+            Some(wild) => wild.clone(),
+            None => input_wild(data).ok_or(format!("no '{}' line in the input", WILD_PREFIX))?,
+        };
+        match wild.iter().find(|&&c| !self.alphabet.contains(c)) {
+            Some(c) => Err(format!("wild card {} is not one of {}", c, self.alphabet)),
+            None => Ok(wild),
+        }
+    }
+}
+
+impl Options {
+    /// Usage: `day07 [path] [--ranking] [--cards 23456789TJQKA] [--hand-size 5] [--joker J]
+    /// [--rule2 joker|wild|ranked-wild] [--wild JQ|input] [--ties reject|share|input-order]`,
+    /// cards go from the weakest to the strongest, part 2 moves the joker to the front.
+    /// `--wild` defaults to the joker.
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut opts = Options {
+            path: None,
+            ranking: false,
+            alphabet: CARDS.to_string(),
+            hand_size: HAND_SIZE,
+            joker: 'J',
+            rule2: Rule2::Joker,
+            wild: Some(vec!['J']),
+            ties: Ties::Reject,
+        };
+        let mut wild_given = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ranking" => opts.ranking = true,
+                "--cards" => opts.alphabet = args.next().ok_or("--cards expects cards")?,
+                "--hand-size" => {
+                    let value = args.next().ok_or("--hand-size expects a number")?;
+                    opts.hand_size = value
+                        .parse()
+                        .map_err(|e| format!("{} for --hand-size {}", e, value))?;
+                }
+                "--joker" => {
+                    let value = args.next().ok_or("--joker expects a card")?;
+                    let mut chars = value.chars();
+                    opts.joker = match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => return Err(format!("--joker expects a single card, got {}", value)),
+                    };
+                }
+                "--rule2" => {
+                    opts.rule2 = match args.next().as_deref() {
+                        Some("joker") => Rule2::Joker,
+                        Some("wild") => Rule2::Wild,
+                        Some("ranked-wild") => Rule2::RankedWild,
+                        v => {
+                            return Err(format!(
+                                "--rule2 expects joker, wild or ranked-wild, got {:?}",
+                                v
+                            ))
+                        }
+                    }
+                }
+                "--ties" => {
+                    opts.ties = match args.next().as_deref() {
+                        Some("reject") => Ties::Reject,
+                        Some("share") => Ties::Share,
+                        Some("input-order") => Ties::InputOrder,
+                        v => {
+                            return Err(format!(
+                                "--ties expects reject, share or input-order, got {:?}",
+                                v
+                            ))
+                        }
+                    }
+                }
+                "--wild" => {
+                    wild_given = true;
+                    opts.wild = match args.next().ok_or("--wild expects cards")?.as_str() {
+                        "input" => None,
+                        cards => Some(cards.chars().collect()),
+                    }
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ if opts.path.is_some() => return Err(format!("unexpected argument {}", arg)),
+                _ => opts.path = Some(arg),
+            }
+        }
+
+        if !wild_given {
+            opts.wild = Some(vec![opts.joker]);
+        }
+        if opts.hand_size == 0 || opts.hand_size > u8::MAX as usize {
+            return Err(format!("--hand-size expects 1 to {}", u8::MAX));
+        }
+        let mut cards: Vec<char> = opts.alphabet.chars().collect();
+        cards.sort();
+        cards.dedup();
+        if cards.len() != opts.alphabet.chars().count() || cards.len() > u8::MAX as usize {
+            return Err(format!(
+                "--cards expects at most {} distinct cards, got {}",
+                u8::MAX,
+                opts.alphabet
+            ));
+        }
+
+        Ok(opts)
+    }
 }
 
 pub fn main() {
-    let given = args()
-        .nth(1)
-        .and_then(|path| fs::read_to_string(path).ok())
-        .unwrap_or("".to_string());
+    let opts = Options::from_args(args().skip(1)).expect("Can't parse arguments");
+    let given = opts.path.as_ref().map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't read {}: {}", path, e))
+    });
 
     let builtin = include_str!("../data.txt");
-    let data = if given.is_empty() { builtin } else { &given };
+    let data = given.as_deref().unwrap_or(builtin);
 
-    let result1 = solve::<Game1>(&data);
+    let game1 = Game1::new(&opts.alphabet, opts.hand_size);
+    let result1 = run("part 1", &game1, data, &opts);
     dbg!(result1);
 
-    let result2 = solve::<Game2>(&data);
+    let (cards, size) = (opts.alphabet.as_str(), opts.hand_size);
+    let result2 = match opts.rule2 {
+        Rule2::Joker => run("part 2", &Game2::new(cards, size, opts.joker), data, &opts),
+        rule2 => {
+            let wild = opts.wild_cards(data).unwrap_or_else(|e| panic!("{}", e));
+            match rule2 {
+                Rule2::Wild => run("part 2", &Wild::new(cards, size, &wild), data, &opts),
+                _ => run("part 2", &RankedWild::new(cards, size, &wild), data, &opts),
+            }
+        }
+    };
     dbg!(result2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "32T3K 765
+T55J5 684
+KK677 28
+KTJJT 220
+QQQJA 483
+";
+
+    #[test]
+    fn test_example() {
+        assert_eq!(Ok(6440), solve(&Game1::default(), EXAMPLE, Ties::Reject));
+        assert_eq!(Ok(5905), solve(&Game2::default(), EXAMPLE, Ties::Reject));
+    }
+
+    #[test]
+    fn test_hand() {
+        let game2 = Game2::default();
+        let ranked: Vec<String> = ranking(&game2, EXAMPLE, Ties::Reject)
+            .unwrap()
+            .iter()
+            .map(|r| r.hand.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "32T3K (one pair)",
+                "KK677 (two pair)",
+                "T55J5 (four of a kind)",
+                "QQQJA (four of a kind)",
+                "KTJJT (four of a kind)",
+            ],
+            ranked
+        );
+
+        let game1 = Game1::default();
+        let hand1 = |cards| Hand::new(&game1, cards).unwrap();
+        let hand2 = |cards| Hand::new(&game2, cards).unwrap();
+        assert!(hand1("33332") > hand1("2AAAA"));
+        assert!(hand1("KTJJT") < hand1("KK677"));
+        assert!(hand2("KTJJT") > hand2("KK677"));
+        assert_eq!(hand1("AKQJT"), hand1("AKQJT"));
+        assert_eq!("full house", hand2("J2233").hand_type.to_string());
+        assert_eq!("five of a kind", hand2("JJJJJ").hand_type.to_string());
+    }
+
+    #[test]
+    fn test_hand_sizes() {
+        let three = Game1::new("abc", 3);
+        let hand = |cards| Hand::new(&three, cards).unwrap();
+        assert_eq!("three of a kind", hand("aaa").hand_type.to_string());
+        assert_eq!("one pair", hand("aba").hand_type.to_string());
+        assert!(hand("cba") < hand("aab"));
+        assert_eq!(
+            Err(HandErr::Size {
+                expected: 3,
+                found: 5
+            }),
+            Hand::new(&three, "abcab")
+        );
+
+        let seven = Game2::new(CARDS, 7, 'J');
+        let hand = |cards| Hand::new(&seven, cards).unwrap();
+        assert_eq!(
+            "three of a kind + three of a kind",
+            hand("2223334").hand_type.to_string()
+        );
+        assert_eq!(
+            "four of a kind + one pair",
+            hand("22J3334").hand_type.to_string()
+        );
+        assert_eq!("seven of a kind", hand("JJJJJJJ").hand_type.to_string());
+        assert!(hand("22J3334") < hand("2222333"));
+        // The alphabet has 13 cards, the hand 7.
+        assert_eq!(
+            "Hand(\"2223334\", HandType([3, 3, 1]), 7 cards)",
+            format!("{:?}", hand("2223334"))
+        );
+
+        let seven = Game1::new(CARDS, 7);
+        let hand = |cards| Hand::new(&seven, cards).unwrap();
+        assert_eq!(HandType(vec![7]), hand("2222222").hand_type);
+        assert_eq!(HandType(vec![5, 2]), hand("3333322").hand_type);
+        assert!(hand("2222222") > hand("3333322"));
+        assert_eq!("three pair", hand("2233445").hand_type.to_string());
+        assert!(hand("2233445") > hand("AAKKQJT"));
+        assert!(hand("2223344") > hand("AAKKQQJ"));
+        assert_eq!("high card", hand("2345678").hand_type.to_string());
+
+        // 94 cards take 7 bits each and group sizes up to 4 more, 40 cards don't fit
+        // into the packed key, 10 do.
+        let alphabet: String = ('!'..='~').collect();
+        let long = Game1::new(&alphabet, 40);
+        let a = Hand::new(&long, &("!".repeat(39) + "~")).unwrap();
+        let b = Hand::new(&long, &("~".to_string() + &"!".repeat(39))).unwrap();
+        assert_eq!((None, None), (a.key, b.key));
+        assert!(a < b);
+        assert!(Hand::new(&Game1::new(&alphabet, 10), &"!".repeat(10))
+            .unwrap()
+            .key
+            .is_some());
+    }
+
+    #[test]
+    fn test_wild() {
+        // A single wild joker is part 2, keeping its rank is not.
+        let wild = Wild::new(CARDS, HAND_SIZE, &['J']);
+        assert_eq!(Ok(5905), solve(&wild, EXAMPLE, Ties::Reject));
+        let ranked = RankedWild::new(CARDS, HAND_SIZE, &['J']);
+        let hand = |cards| Hand::new(&ranked, cards).unwrap();
+        assert_eq!("four of a kind", hand("KTJJT").hand_type.to_string());
+        assert!(hand("JKKK2") > hand("TTTT2"));
+        assert!(Hand::new(&wild, "JKKK2").unwrap() < Hand::new(&wild, "TTTT2").unwrap());
+
+        let two = Wild::new(CARDS, HAND_SIZE, &['A', '2']);
+        let hand = |cards| Hand::new(&two, cards).unwrap();
+        assert_eq!("five of a kind", hand("A2KKK").hand_type.to_string());
+        assert_eq!("one pair", hand("A3456").hand_type.to_string());
+        assert!(hand("2KKKK") < hand("AKKKK"));
+        assert!(hand("AKKK3") < hand("3KKKA"));
+
+        let data = format!("wild: T Q\n{}", EXAMPLE);
+        assert_eq!(Some(vec!['T', 'Q']), input_wild(&data));
+        let from_input = Wild::new(CARDS, HAND_SIZE, &input_wild(&data).unwrap());
+        let hand = Hand::new(&from_input, "QQQJA").unwrap();
+        assert_eq!("four of a kind", hand.hand_type.to_string());
+        assert_eq!(5, ranking(&from_input, &data, Ties::Reject).unwrap().len());
+
+        let opts = Options::from_args(["--wild", "input"].map(String::from).into_iter()).unwrap();
+        assert_eq!(Ok(vec!['T', 'Q']), opts.wild_cards(&data));
+        assert!(opts.wild_cards(EXAMPLE).is_err());
+        let opts = Options::from_args(["--wild", "X"].map(String::from).into_iter()).unwrap();
+        assert!(opts.wild_cards(EXAMPLE).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let game1 = Game1::default();
+        let check = |data: &str| solve(&game1, data, Ties::Reject).map_err(|e| e.to_string());
+        assert_eq!(
+            Err("line 2: invalid card 'X' at column 3".to_string()),
+            check("32T3K 765\nT5XJ5 684")
+        );
+        assert_eq!(
+            Err("line 1: expected 5 cards, got 4".to_string()),
+            check("32T3 765")
+        );
+        assert_eq!(
+            Err("line 3: malformed hand: expected 'cards bid'".to_string()),
+            check("32T3K 765\n\nT55J5")
+        );
+        assert!(check("32T3K 765 1").is_err());
+        assert!(check("32T3K -765").is_err());
+        assert!(check("32T3K 765\n\n").is_ok());
+
+        let big = format!("32T3K {}\nT55J5 {}", u64::MAX / 2, u64::MAX / 2);
+        assert_eq!(Err(InputErr::Overflow), solve(&game1, &big, Ties::Reject));
+        let fits = format!("32T3K 1\nT55J5 {}", u64::MAX / 2);
+        assert_eq!(Ok(u64::MAX / 2 * 2 + 1), solve(&game1, &fits, Ties::Reject));
+    }
+
+    #[test]
+    fn test_ties() {
+        let game1 = Game1::default();
+        let data = "KK677 2\n32T3K 3\nKK677 5\nT55J5 7";
+        assert_eq!(
+            Err("line 3: hand KK677 is already dealt at line 1".to_string()),
+            solve(&game1, data, Ties::Reject).map_err(|e| e.to_string())
+        );
+
+        let ranks = |ties| {
+            ranking(&game1, data, ties)
+                .unwrap()
+                .iter()
+                .map(|r| (r.rank, r.line))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![(1, 2), (2, 1), (2, 3), (4, 4)], ranks(Ties::Share));
+        assert_eq!(
+            vec![(1, 2), (2, 1), (3, 3), (4, 4)],
+            ranks(Ties::InputOrder)
+        );
+        assert_eq!(
+            Ok(3 + 2 * 2 + 2 * 5 + 4 * 7),
+            solve(&game1, data, Ties::Share)
+        );
+        assert_eq!(
+            Ok(3 + 2 * 2 + 3 * 5 + 4 * 7),
+            solve(&game1, data, Ties::InputOrder)
+        );
+    }
+
+    #[test]
+    fn test_matches_packed() {
+        // Hands with 5 cards always take the fast path.
+        let game1 = Game1::default();
+        let mut hands: Vec<Hand<Game1>> = EXAMPLE
+            .lines()
+            .map(|l| Hand::new(&game1, &l[..5]).unwrap())
+            .collect();
+        hands.sort();
+        let slow: Vec<_> = hands
+            .iter()
+            .map(|h| (h.hand_type.clone(), h.values.clone()))
+            .collect();
+        let mut sorted = slow.clone();
+        sorted.sort();
+        assert_eq!(sorted, slow);
+        assert!(hands.iter().all(|h| h.key.is_some()));
+    }
+
+    #[test]
+    fn test_args() {
+        let parse = |args: &[&str]| Options::from_args(args.iter().map(|a| a.to_string()));
+        let opts = parse(&["hands.txt", "--ranking"]).unwrap();
+        assert_eq!(Some("hands.txt".to_string()), opts.path);
+        assert!(opts.ranking);
+
+        assert_eq!(
+            Err("unknown option --rank".to_string()),
+            parse(&["hands.txt", "--rank"]).map(|_| ())
+        );
+        assert_eq!(
+            Err("unexpected argument b.txt".to_string()),
+            parse(&["a.txt", "b.txt"]).map(|_| ())
+        );
+    }
+}
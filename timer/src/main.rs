@@ -6,7 +6,7 @@ fn puzzles() -> &'static [(fn(), &'static str)] {
         (day02::main, "2"),
         (day03::main, "3"),
         (day04::main, "4"),
-        // (day05::main, "5"),
+        (day05::main, "5"),
         (day06::main, "6"),
         (day07::main, "7"),
         (day08::main, "8"),